@@ -0,0 +1,249 @@
+use crate::similarity;
+
+/// Street abbreviations as they appear in the registries and the words they stand for.
+const ABBREVIATIONS: [(&str, &str); 6] = [
+    ("ul.", "ulica"),
+    ("c.", "cesta"),
+    ("cst.", "cesta"),
+    ("nab.", "nabrežje"),
+    ("nas.", "naselje"),
+    ("šet.", "šetališče"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Address {
+    pub street: Option<String>,
+    pub house_number: Option<String>,
+    pub postcode: Option<String>,
+    pub city: Option<String>,
+}
+
+impl Address {
+    /// Key that is the same however the registries write the address, e.g.
+    /// "2000|partizanska ulica|5" for "Partizanska ul. 5, 2000 Maribor". Addresses without a
+    /// postcode, street or house number have none.
+    pub fn key(&self) -> Option<String> {
+        Some(format!(
+            "{}|{}|{}",
+            self.postcode.as_ref()?,
+            similarity::words(self.street.as_ref()?).join(" "),
+            self.house_number.as_ref()?.to_lowercase()
+        ))
+    }
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!value.is_empty()).then_some(value)
+}
+
+fn is_postcode(token: &str) -> bool {
+    token.len() == 4 && token.chars().all(|c| c.is_ascii_digit())
+}
+
+fn is_house_number(token: &str) -> bool {
+    let token = token.to_lowercase();
+    token.starts_with(|c: char| c.is_ascii_digit()) || token == "b.š." || token == "bš"
+}
+
+fn is_house_number_suffix(token: &str) -> bool {
+    token.chars().count() == 1 && token.chars().all(char::is_alphabetic)
+}
+
+fn is_uppercase_word(token: &str) -> bool {
+    token.chars().any(char::is_alphabetic) && token.chars().all(|c| !c.is_lowercase())
+}
+
+/// Expands a street abbreviation. It is written in capitals when the word next to it is,
+/// which is the next word or the one before when the abbreviation comes last, and
+/// otherwise capitalized as the registry wrote it.
+fn expand(tokens: &[&str], position: usize) -> String {
+    let token = tokens[position];
+    let lowercase = token.to_lowercase();
+    let Some((_, long)) = ABBREVIATIONS.iter().find(|(short, _)| *short == lowercase) else {
+        return token.to_string();
+    };
+    let neighbour = tokens[position + 1..]
+        .iter()
+        .find(|token| token.chars().any(char::is_alphabetic))
+        .or_else(|| {
+            tokens[..position]
+                .iter()
+                .rev()
+                .find(|token| token.chars().any(char::is_alphabetic))
+        });
+    match neighbour {
+        Some(neighbour) if is_uppercase_word(neighbour) => long.to_uppercase(),
+        None if is_uppercase_word(token) => long.to_uppercase(),
+        _ if token.starts_with(char::is_uppercase) => {
+            let mut chars = long.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        }
+        _ => long.to_string(),
+    }
+}
+
+/// Normalizes the street name by expanding abbreviations, so "Tržaška c." and
+/// "Tržaška cesta" end up the same.
+pub fn normalize_street(street: &str) -> String {
+    let tokens: Vec<&str> = street.split_whitespace().collect();
+    (0..tokens.len())
+        .map(|position| expand(&tokens, position))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Normalizes a city name for exact filtering, e.g. " Murska  sobota" into "MURSKA SOBOTA".
+pub fn normalize_city(city: &str) -> String {
+    non_empty(city).unwrap_or_default().to_uppercase()
+}
+
+/// Splits the street part ("Tržaška c. 12 a") into the street and the house number.
+fn parse_street(street_part: &str) -> (Option<String>, Option<String>) {
+    let tokens: Vec<&str> = street_part.split_whitespace().collect();
+    let Some(number_start) = tokens.iter().rposition(|token| is_house_number(token)) else {
+        return (non_empty(street_part).map(|s| normalize_street(&s)), None);
+    };
+
+    let number_tokens = &tokens[number_start..];
+    if number_start == 0
+        || !number_tokens[1..]
+            .iter()
+            .all(|token| is_house_number_suffix(token))
+    {
+        return (non_empty(street_part).map(|s| normalize_street(&s)), None);
+    }
+
+    (
+        non_empty(&tokens[..number_start].join(" ")).map(|s| normalize_street(&s)),
+        non_empty(&number_tokens.concat()),
+    )
+}
+
+/// Splits the postal part ("1000 Ljubljana") into the postcode and the city.
+fn parse_post(post_part: &str) -> Option<(String, Option<String>)> {
+    let post_part = post_part.trim();
+    let (postcode, city) = post_part
+        .split_once(char::is_whitespace)
+        .unwrap_or((post_part, ""));
+    is_postcode(postcode).then(|| {
+        (
+            postcode.to_string(),
+            non_empty(city).map(|c| normalize_city(&c)),
+        )
+    })
+}
+
+/// Parses a Slovenian address in the "Ulica 12a, 1000 Ljubljana" format. Parts that can not
+/// be recognized are left empty rather than guessed.
+pub fn parse(address: &str) -> Address {
+    if let Some((street_part, post_part)) = address.rsplit_once(',')
+        && let Some((postcode, city)) = parse_post(post_part)
+    {
+        let (street, house_number) = parse_street(street_part);
+        return Address {
+            street,
+            house_number,
+            postcode: Some(postcode),
+            city,
+        };
+    }
+
+    // Some records leave out the comma, so look for the postcode from the end instead.
+    let tokens: Vec<&str> = address.split_whitespace().collect();
+    if let Some(position) = tokens.iter().rposition(|token| is_postcode(token))
+        && position > 0
+    {
+        let (street, house_number) = parse_street(&tokens[..position].join(" "));
+        return Address {
+            street,
+            house_number,
+            postcode: Some(tokens[position].to_string()),
+            city: non_empty(&tokens[position + 1..].join(" ")).map(|c| normalize_city(&c)),
+        };
+    }
+
+    let (street, house_number) = parse_street(address);
+    Address {
+        street,
+        house_number,
+        ..Address::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(street: &str, house_number: &str, postcode: &str, city: &str) -> Address {
+        Address {
+            street: Some(street.to_string()),
+            house_number: Some(house_number.to_string()),
+            postcode: Some(postcode.to_string()),
+            city: Some(city.to_string()),
+        }
+    }
+
+    #[test]
+    fn parses_an_address_with_a_comma() {
+        assert_eq!(
+            parse("Partizanska ul. 5, 2000 Maribor"),
+            address("Partizanska ulica", "5", "2000", "MARIBOR")
+        );
+    }
+
+    #[test]
+    fn parses_an_address_without_a_comma() {
+        assert_eq!(
+            parse("Tržaška c. 12a 1000 Ljubljana"),
+            address("Tržaška cesta", "12a", "1000", "LJUBLJANA")
+        );
+    }
+
+    #[test]
+    fn joins_a_separated_house_number_suffix() {
+        assert_eq!(
+            parse("Tržaška cesta 12 a, 1000 Ljubljana"),
+            address("Tržaška cesta", "12a", "1000", "LJUBLJANA")
+        );
+    }
+
+    #[test]
+    fn parses_an_uppercase_house_without_a_number() {
+        let parsed = parse("PODKRAJ B.Š., 1000 LJUBLJANA");
+        assert_eq!(parsed.house_number.as_deref(), Some("B.Š."));
+        assert_eq!(parsed.key().as_deref(), Some("1000|podkraj|b.š."));
+    }
+
+    #[test]
+    fn expands_abbreviations_in_the_case_of_the_words_next_to_them() {
+        assert_eq!(normalize_street("Tržaška C."), "Tržaška Cesta");
+        assert_eq!(normalize_street("Tržaška c."), "Tržaška cesta");
+        assert_eq!(normalize_street("TRŽAŠKA C."), "TRŽAŠKA CESTA");
+        assert_eq!(normalize_street("C. v Mestni log"), "Cesta v Mestni log");
+        assert_eq!(normalize_street("C. V MESTNI LOG"), "CESTA V MESTNI LOG");
+        assert_eq!(normalize_street("UL."), "ULICA");
+    }
+
+    #[test]
+    fn keeps_a_house_without_a_number() {
+        assert_eq!(
+            parse("Podkraj b.š., 1000 Ljubljana")
+                .house_number
+                .as_deref(),
+            Some("b.š.")
+        );
+    }
+
+    #[test]
+    fn keys_the_same_address_written_differently() {
+        assert_eq!(
+            parse("Partizanska ul. 5, 2000 Maribor").key(),
+            parse("PARTIZANSKA ULICA 5 2000 MARIBOR").key()
+        );
+        assert_eq!(parse("Partizanska ulica 5").key(), None);
+    }
+}
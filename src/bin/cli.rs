@@ -1,77 +1,370 @@
-use clap::Parser;
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand};
 use fuzzija::config::AppConfig;
+use fuzzija::geo::{BoundingBox, Point};
+use fuzzija::reconcile::ReconcileConfig;
+use fuzzija::search::{Highlight, MatchMode, SearchOptions, SortOrder};
 use fuzzija::tpconfig::SourceName;
 use fuzzija::*;
 use log::*;
 use std::collections::HashSet;
-use std::sync::Arc;
+use std::fs::File;
+use std::path::PathBuf;
 use tokio::task::JoinSet;
 
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    #[command(flatten)]
+    app: AppConfig,
+    /// Only return companies with this legal form, e.g. "d.o.o."
+    #[arg(long)]
+    legal_form: Option<String>,
+    /// Only return entities registered under this postcode
+    #[arg(long)]
+    postcode: Option<String>,
+    /// Only return entities registered in this city
+    #[arg(long)]
+    city: Option<String>,
+    /// Only return taxpayers that are, or with `false` are not, registered for VAT
+    #[arg(long)]
+    vat_registered: Option<bool>,
+    /// Only return entities registered on or after this date, e.g. `2020-01-31`
+    #[arg(long)]
+    registered_after: Option<NaiveDate>,
+    /// Only return entities registered on or before this date
+    #[arg(long)]
+    registered_before: Option<NaiveDate>,
+    /// Only return entities with this registration status
+    #[arg(long)]
+    status: Option<String>,
+    /// Only return entities in this SKD section, e.g. `F` for construction, or under this
+    /// activity code, e.g. `41` or `41.2`
+    #[arg(long)]
+    activity: Option<String>,
+    /// Drill down with a `dimension:value` facet filter, e.g. `city:MARIBOR`
+    #[arg(long, value_parser = parse_filter)]
+    filter: Vec<(String, String)>,
+    /// Count hits per value of these facet dimensions, e.g. `legal_form,city`
+    #[arg(long, value_delimiter = ',')]
+    facets: Vec<String>,
+    /// Search around `lat,lon` or a place name, e.g. `Maribor`
+    #[arg(long, value_parser = parse_point)]
+    near: Option<Point>,
+    /// Only return hits within this many kilometers of `--near`
+    #[arg(long, requires = "near")]
+    radius_km: Option<f64>,
+    /// Only return hits inside `min_lat,min_lon,max_lat,max_lon`
+    #[arg(long, value_parser = parse_bounding_box)]
+    bbox: Option<BoundingBox>,
+    #[arg(long, value_enum, default_value_t = SortOrder::Relevance)]
+    sort: SortOrder,
+    /// Print the matched parts of names and addresses
+    #[arg(long, default_value_t = false)]
+    highlight: bool,
+    /// Print how the query was parsed and analyzed and how each hit was scored
+    #[arg(long, default_value_t = false)]
+    explain: bool,
+    #[arg(long, value_enum, default_value_t = MatchMode::Lexical)]
+    mode: MatchMode,
+    /// Rerank the top hits by string similarity to the query and print their confidence
+    #[arg(long, default_value_t = false)]
+    rerank: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+fn parse_filter(raw: &str) -> Result<(String, String), String> {
+    search::parse_filter(raw).ok_or_else(|| format!("expected dimension:value, got {:?}", raw))
+}
+
+fn parse_point(raw: &str) -> Result<Point, String> {
+    geo::parse_point(raw).ok_or_else(|| format!("expected lat,lon or a known place, got {:?}", raw))
+}
+
+fn parse_bounding_box(raw: &str) -> Result<BoundingBox, String> {
+    geo::parse_bounding_box(raw)
+        .ok_or_else(|| format!("expected min_lat,min_lon,max_lat,max_lon, got {:?}", raw))
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Show when and from what each index was built
+    Stats,
+    /// Match every row of a CSV of counterparties to a registered entity
+    Reconcile {
+        /// CSV file with a header row
+        #[arg(long)]
+        input: PathBuf,
+        /// Where to write the input with the match columns appended [default: <input>_matched.csv]
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Column holding the name to match
+        #[arg(long)]
+        name_col: String,
+        /// Column holding the address, which makes matches more certain
+        #[arg(long)]
+        address_col: Option<String>,
+        #[arg(long, default_value_t = ',')]
+        delimiter: char,
+        /// Leave the match columns empty below this confidence
+        #[arg(long, default_value_t = 0.6)]
+        min_confidence: f64,
+    },
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     env_logger::init();
-    let app_config = AppConfig::parse();
-    let (index_map, reader_map) = indexer::init(&app_config).await?;
+    let cli = Cli::parse();
+    let app_config = cli.app;
+    let (index_map, mut reader_map) = indexer::init(&app_config).await?;
 
     if app_config.reindex {
         info!("Reindexing.");
+        let indexes_folder = PathBuf::from(&app_config.indexes_folder);
         let mut collection_tasks = JoinSet::new();
         for (_, source_config) in tpconfig::available_sources() {
-            collection_tasks.spawn(sources::collect(app_config.clone(), &source_config));
+            collection_tasks.spawn(sources::collect(app_config.clone(), source_config));
         }
 
         let mut indexing_tasks = JoinSet::new();
         while let Some(Ok(result)) = collection_tasks.join_next().await {
-            let (source_name, path) = result.unwrap();
+            let (source_name, path) = match result {
+                Ok(collected) => collected,
+                Err(e) => {
+                    error!("Collection failed, keeping existing index: {}", e);
+                    continue;
+                }
+            };
             info!("Collected data from {} to {}", source_name, path.display());
 
-            let indexes = Arc::clone(&index_map);
+            let indexes_folder = indexes_folder.clone();
             indexing_tasks.spawn(async move {
-                let maybe_index = {
-                    let locked_indexes = indexes.lock().await;
-                    locked_indexes.get(&source_name).cloned()
-                };
-
-                if maybe_index.is_none() || 1 == 1 {
-                    indexer::index_source(source_name, maybe_index, path).await
-                } else {
-                    info!(
-                        "Index for '{}' already exists, skipping indexing.",
-                        source_name
-                    );
-                    Ok(())
-                }
+                indexer::index_source(source_name, indexes_folder, path)
+                    .await
+                    .map(|index| (source_name, index))
             });
         }
 
         while let Some(Ok(result)) = indexing_tasks.join_next().await {
-            if let Err(e) = result {
-                error!("Indexing failed: {}", e);
-                continue;
+            match result {
+                Ok((source_name, index)) => {
+                    index_map.lock().await.insert(source_name, index);
+                }
+                Err(e) => {
+                    error!("Indexing failed, keeping existing index: {}", e);
+                    continue;
+                }
             }
         }
+        reader_map = search::open_readers(&index_map).await;
+        linking::rebuild(&reader_map, &indexes_folder).await?;
         info!("Indexing finished.");
     }
 
-    if let Some(query) = app_config.query {
-        info!("Searching for \"{}\"", query);
-        let search_results = search::search_indexes(
+    if let Some(Command::Stats) = cli.command {
+        let indexes_folder = PathBuf::from(&app_config.indexes_folder);
+        for stats in metadata::source_stats(&reader_map, &indexes_folder).await {
+            println!("{}:", stats.source_name);
+            println!("\t- index: {}", stats.index_path);
+            println!("\t- documents: {}", stats.documents);
+            match stats.metadata {
+                Some(index_metadata) => {
+                    println!("\t- source url: {}", index_metadata.source_url);
+                    println!("\t- archive sha256: {}", index_metadata.archive_sha256);
+                    if let Some(downloaded_at) = index_metadata.downloaded_at {
+                        println!("\t- downloaded at: {}", downloaded_at);
+                    }
+                    if let Some(upstream_file_date) = index_metadata.upstream_file_date {
+                        println!("\t- upstream file date: {}", upstream_file_date);
+                    }
+                    println!("\t- indexed at: {}", index_metadata.indexed_at);
+                    println!(
+                        "\t- rows: {} ({} skipped)",
+                        index_metadata.rows, index_metadata.skipped_rows
+                    );
+                    if index_metadata.invalid_vat_ids > 0 {
+                        println!(
+                            "\t- invalid VAT ids: {} (e.g. {})",
+                            index_metadata.invalid_vat_ids,
+                            index_metadata.invalid_vat_id_sample.join(", ")
+                        );
+                    }
+                    if index_metadata.invalid_company_ids > 0 {
+                        println!(
+                            "\t- invalid company ids: {} (e.g. {})",
+                            index_metadata.invalid_company_ids,
+                            index_metadata.invalid_company_id_sample.join(", ")
+                        );
+                    }
+                    if index_metadata.unmatched_postcodes > 0 {
+                        println!(
+                            "\t- unmatched postcodes: {}",
+                            index_metadata.unmatched_postcodes
+                        );
+                    }
+                    if !index_metadata.missing_columns.is_empty() {
+                        println!(
+                            "\t- missing columns: {}",
+                            index_metadata.missing_columns.join(", ")
+                        );
+                    }
+                    println!("\t- schema version: {}", index_metadata.schema_version);
+                }
+                None => println!("\t- no index metadata"),
+            }
+        }
+    }
+
+    if let Some(Command::Reconcile {
+        input,
+        output,
+        name_col,
+        address_col,
+        delimiter,
+        min_confidence,
+    }) = cli.command
+    {
+        let output = output.unwrap_or_else(|| {
+            let file_stem = input.file_stem().unwrap_or_default().to_string_lossy();
+            input.with_file_name(format!("{}_matched.csv", file_stem))
+        });
+        info!("Reconciling {} into {}", input.display(), output.display());
+        let sources = search::snapshot_sources(
             &index_map,
             &reader_map,
-            HashSet::from([
+            &HashSet::from([
                 SourceName::PravneOsebe,
                 SourceName::FizicneOsebe,
                 SourceName::PoslovniRegisterSlovenije,
             ]),
-            query,
-            None,
+        )
+        .await;
+        let summary = reconcile::reconcile_csv(
+            &sources,
+            File::open(&input)?,
+            File::create(&output)?,
+            &ReconcileConfig {
+                name_column: name_col,
+                address_column: address_col,
+                delimiter: u8::try_from(delimiter)?,
+                min_confidence,
+            },
+            &mut |_| {},
+        )?;
+        println!(
+            "Matched {} of {} rows, {} of them ambiguous, into {}",
+            summary.matched,
+            summary.rows,
+            summary.ambiguous,
+            output.display()
+        );
+    }
+
+    if let Some(query) = app_config.query {
+        info!("Searching for \"{}\"", query);
+        let selected_sources = HashSet::from([
+            SourceName::PravneOsebe,
+            SourceName::FizicneOsebe,
+            SourceName::PoslovniRegisterSlovenije,
+        ]);
+        let options = SearchOptions {
+            legal_form: cli.legal_form,
+            postcode: cli.postcode,
+            city: cli.city,
+            vat_registered: cli.vat_registered,
+            registered_after: cli.registered_after,
+            registered_before: cli.registered_before,
+            status: cli.status,
+            activity: cli.activity,
+            filters: cli.filter,
+            facets: cli.facets,
+            near: cli.near,
+            radius_km: cli.radius_km,
+            bounding_box: cli.bbox,
+            sort: cli.sort,
+            highlight: cli.highlight.then(|| Highlight {
+                pre_tag: "[".to_string(),
+                post_tag: "]".to_string(),
+            }),
+            explain: cli.explain,
+            mode: cli.mode,
+            rerank: cli.rerank,
+            ..SearchOptions::default()
+        };
+        let search_response = search::search_indexes(
+            &index_map,
+            &reader_map,
+            selected_sources.clone(),
+            query.clone(),
+            options.clone(),
         )
         .await?;
+        let hits: usize = search_response.results.values().map(Vec::len).sum();
+        let links = linking::load(&reader_map, &PathBuf::from(&app_config.indexes_folder)).await;
 
-        for (source_name, results) in search_results {
+        for (source_name, results) in search_response.results {
             println!("{}:", source_name);
-            for (score, _, json_document) in results {
-                println!("\t- {:.2} {}", score, json_document);
+            if let Some(query_debug) = search_response.debug.get(&source_name) {
+                println!("\tparsed query: {}", query_debug.parsed_query);
+                for (field_name, tokens) in &query_debug.analyzed {
+                    println!("\tanalyzed {}: {:?}", field_name, tokens);
+                }
+            }
+            for result in results {
+                match result.distance_km {
+                    Some(distance_km) => println!(
+                        "\t- {:.2} {} ({:.1} km)",
+                        result.score, result.json, distance_km
+                    ),
+                    None => println!("\t- {:.2} {}", result.score, result.json),
+                }
+                if let Some(linked) = links.linked(source_name, &result.document) {
+                    println!(
+                        "\t\tlinked: {} {} in {}",
+                        linked.id, linked.name, linked.source_name
+                    );
+                }
+                if let Some(confidence) = result.confidence {
+                    println!("\t\tconfidence: {:.2}", confidence);
+                }
+                if let Some(similarity) = result.similarity {
+                    println!("\t\tsimilarity: {:.2}", similarity);
+                }
+                for (field_name, snippet) in result.snippets {
+                    println!("\t\t{}: {}", field_name, snippet);
+                }
+                if let Some(explanation) = result.explanation {
+                    println!("\t\t{}", serde_json::to_string_pretty(&explanation)?);
+                }
+            }
+        }
+
+        for (dimension, counts) in search_response.facets {
+            let mut counts: Vec<(String, u64)> = counts.into_iter().collect();
+            counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            println!("{}:", dimension);
+            for (value, count) in counts {
+                println!("\t- {} ({})", value, count);
+            }
+        }
+
+        if hits < spellcheck::SPARSE_HITS {
+            let suggestions = spellcheck::suggest(
+                &index_map,
+                &reader_map,
+                &selected_sources,
+                &query,
+                &options,
+                3,
+            )
+            .await?;
+            if !suggestions.is_empty() {
+                println!("Did you mean:");
+                for suggestion in suggestions {
+                    println!("\t- {} ({} hits)", suggestion.query, suggestion.hits);
+                }
             }
         }
     }
@@ -1,24 +1,38 @@
-use axum::extract::Query;
-use axum::http::Method;
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, Path, Query};
+use axum::http::header;
+use axum::http::{Method, StatusCode};
 use axum::{
     Router,
     response::{IntoResponse, Json},
-    routing::get,
+    routing::{get, post},
 };
-use clap::Parser;
+use chrono::NaiveDate;
+use clap::{Parser, ValueEnum};
 use fuzzija::config::{AppConfig, ServerConfig};
 use fuzzija::indexer::IndexMap;
-use fuzzija::search::{ReaderMap, SearchResults};
+use fuzzija::jobs::{Job, JobFormat, JobState};
+use fuzzija::linking::{LinkTable, Linked};
+use fuzzija::metadata::SourceStats;
+use fuzzija::search::{
+    AddressGroup, FacetCounts, Highlight, MatchMode, QueryDebug, ReaderMap, SearchOptions,
+    SearchResponse, SortOrder, Suggestion,
+};
+use fuzzija::spellcheck::{QuerySuggestion, SPARSE_HITS};
 use fuzzija::tpconfig::SourceName;
-use fuzzija::{indexer, search};
+use fuzzija::validation::VatIdCheck;
+use fuzzija::{geo, indexer, jobs, linking, metadata, search, spellcheck, validation};
 use log::{error, info};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use tokio::signal::unix::{SignalKind, signal};
 use tokio::sync::Mutex;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinSet;
 use tower_http::cors::{Any, CorsLayer};
 
 #[derive(Parser, Debug)]
@@ -29,10 +43,145 @@ struct Config {
     server: ServerConfig,
 }
 
+#[derive(Clone)]
+struct AppState {
+    indexes: Arc<Mutex<IndexMap>>,
+    readers: Arc<Mutex<ReaderMap>>,
+    /// Sole proprietors linked between the tax registry and the business register.
+    links: Arc<LinkTable>,
+    indexes_folder: PathBuf,
+    max_batch_size: usize,
+    jobs_folder: PathBuf,
+    /// Queues jobs for the worker that runs them.
+    job_sender: UnboundedSender<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct SearchQuery {
     query: String,
     limit: Option<usize>,
+    legal_form: Option<String>,
+    postcode: Option<String>,
+    city: Option<String>,
+    /// Only return taxpayers that are, or with `false` are not, registered for VAT.
+    vat_registered: Option<bool>,
+    /// Only return entities registered on or after this date, e.g. `2020-01-31`.
+    registered_after: Option<NaiveDate>,
+    registered_before: Option<NaiveDate>,
+    /// Registration status as the business register writes it.
+    status: Option<String>,
+    /// SKD section, e.g. `F` for construction, or activity code, e.g. `41` or `41.2`.
+    activity: Option<String>,
+    /// Comma separated facet dimensions to count, e.g. `legal_form,city`.
+    facets: Option<String>,
+    /// Comma separated `dimension:value` drill down filters, e.g. `city:MARIBOR`.
+    filter: Option<String>,
+    /// `lat,lon` or a place name, e.g. `Maribor`.
+    near: Option<String>,
+    radius_km: Option<f64>,
+    /// `min_lat,min_lon,max_lat,max_lon`.
+    bbox: Option<String>,
+    sort: Option<SortOrder>,
+    /// Return highlighted snippets of the matched fields.
+    highlight: Option<bool>,
+    highlight_pre: Option<String>,
+    highlight_post: Option<String>,
+    /// Return score explanations, the parsed query and the analyzer output.
+    explain: Option<bool>,
+    /// `phonetic` also matches names that sound the same, e.g. "Sustar" for "Šuštar", and
+    /// `name` matches the words of a name in any order.
+    mode: Option<MatchMode>,
+    /// Rerank the top hits by string similarity and return a `confidence` per hit.
+    rerank: Option<bool>,
+    /// How many "did you mean" suggestions to return when there are few hits, 3 by default.
+    suggestions: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AutocompleteQuery {
+    q: String,
+    /// Comma separated sources to suggest from, e.g. `pravne-osebe,fizicne-osebe`.
+    sources: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AddressQuery {
+    q: String,
+    /// Comma separated sources to look in, e.g. `pravne-osebe,fizicne-osebe`.
+    sources: Option<String>,
+    /// Most addresses to return, 10 by default.
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BatchRequest {
+    queries: Vec<BatchQuery>,
+}
+
+/// One query of a batch, either free text in `query` or a structured `name` and `address`
+/// with optional filters.
+#[derive(Debug, Serialize, Deserialize)]
+struct BatchQuery {
+    query: Option<String>,
+    name: Option<String>,
+    address: Option<String>,
+    legal_form: Option<String>,
+    postcode: Option<String>,
+    city: Option<String>,
+    vat_registered: Option<bool>,
+    registered_after: Option<NaiveDate>,
+    registered_before: Option<NaiveDate>,
+    status: Option<String>,
+    activity: Option<String>,
+    limit: Option<usize>,
+    /// Sources to search, e.g. `["pravne-osebe"]`, all of them by default.
+    #[serde(default)]
+    sources: Vec<String>,
+    mode: Option<MatchMode>,
+    rerank: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BatchResult {
+    pub results: Vec<DocumentResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Parses source names like `pravne-osebe`, defaulting to all sources when there are none.
+/// Returns `None` when one of them is not known.
+fn parse_sources<'a>(sources: impl Iterator<Item = &'a str>) -> Option<HashSet<SourceName>> {
+    let selected_sources = sources
+        .map(|source| SourceName::from_str(source, true).ok())
+        .collect::<Option<HashSet<SourceName>>>()?;
+    if selected_sources.is_empty() {
+        Some(HashSet::from([
+            SourceName::PravneOsebe,
+            SourceName::FizicneOsebe,
+            SourceName::PoslovniRegisterSlovenije,
+        ]))
+    } else {
+        Some(selected_sources)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JobQuery {
+    name_col: String,
+    address_col: Option<String>,
+    /// `csv`, the default, or `jsonl`.
+    format: Option<JobFormat>,
+    delimiter: Option<char>,
+    min_confidence: Option<f64>,
+}
+
+fn split_list(value: &Option<String>) -> impl Iterator<Item = &str> {
+    value
+        .iter()
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
 }
 
 #[tokio::main]
@@ -45,16 +194,42 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     info!("Booting server on {}", server_address);
 
     let (index_map, reader_map) = indexer::init(&app_config).await?;
+    let links =
+        Arc::new(linking::load(&reader_map, &PathBuf::from(&app_config.indexes_folder)).await);
+    let jobs_folder = PathBuf::from(&server_config.jobs_folder);
+    std::fs::create_dir_all(&jobs_folder)?;
+    let job_sender = jobs::start_worker(index_map.clone(), reader_map.clone(), jobs_folder.clone());
 
     let cors_layer = CorsLayer::new()
         .allow_origin(Any)
-        .allow_methods([Method::GET]);
+        .allow_methods([Method::GET, Method::POST]);
 
     let app = Router::new()
         .route("/", get(|| async { "Ok." }))
         .route("/search", get(search))
+        .route("/search/batch", post(search_batch))
+        .route("/sources", get(sources))
+        .route("/autocomplete", get(autocomplete))
+        .route(
+            "/jobs",
+            post(create_job).layer(DefaultBodyLimit::max(
+                server_config.max_upload_mb * 1024 * 1024,
+            )),
+        )
+        .route("/jobs/{id}", get(job_status))
+        .route("/jobs/{id}/result", get(job_result))
+        .route("/validate/vat/{id}", get(validate_vat_id))
+        .route("/addresses/search", get(search_addresses))
         .layer(cors_layer)
-        .with_state((index_map, reader_map));
+        .with_state(AppState {
+            indexes: index_map,
+            readers: reader_map,
+            links,
+            indexes_folder: PathBuf::from(&app_config.indexes_folder),
+            max_batch_size: server_config.max_batch_size,
+            jobs_folder,
+            job_sender,
+        });
 
     let listener = tokio::net::TcpListener::bind(server_address).await.unwrap();
 
@@ -94,13 +269,75 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 }
 
 async fn search(
-    state: axum::extract::State<(Arc<Mutex<IndexMap>>, Arc<Mutex<ReaderMap>>)>,
+    state: axum::extract::State<AppState>,
     search_query: Query<SearchQuery>,
 ) -> axum::response::Result<impl IntoResponse> {
-    let (indexes, readers) = state.0;
+    let AppState {
+        indexes,
+        readers,
+        links,
+        ..
+    } = state.0;
 
+    if search_query.limit == Some(0) {
+        return Err((StatusCode::BAD_REQUEST, "Limit must be at least 1").into());
+    }
     let query: String = search_query.query.clone();
-    let maybe_limit: Option<usize> = search_query.limit.clone();
+    let near = match search_query.near.as_deref().map(geo::parse_point) {
+        Some(None) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Invalid near, expected lat,lon or a known place",
+            )
+                .into());
+        }
+        near => near.flatten(),
+    };
+    let bounding_box = match search_query.bbox.as_deref().map(geo::parse_bounding_box) {
+        Some(None) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Invalid bbox, expected min_lat,min_lon,max_lat,max_lon",
+            )
+                .into());
+        }
+        bounding_box => bounding_box.flatten(),
+    };
+    let options = SearchOptions {
+        limit: search_query.limit,
+        legal_form: search_query.legal_form.clone(),
+        postcode: search_query.postcode.clone(),
+        city: search_query.city.clone(),
+        vat_registered: search_query.vat_registered,
+        registered_after: search_query.registered_after,
+        registered_before: search_query.registered_before,
+        status: search_query.status.clone(),
+        activity: search_query.activity.clone(),
+        filters: split_list(&search_query.filter)
+            .filter_map(search::parse_filter)
+            .collect(),
+        facets: split_list(&search_query.facets).map(String::from).collect(),
+        near,
+        radius_km: search_query.radius_km,
+        bounding_box,
+        sort: search_query.sort.unwrap_or_default(),
+        highlight: search_query.highlight.unwrap_or(false).then(|| {
+            let default = Highlight::default();
+            Highlight {
+                pre_tag: search_query
+                    .highlight_pre
+                    .clone()
+                    .unwrap_or(default.pre_tag),
+                post_tag: search_query
+                    .highlight_post
+                    .clone()
+                    .unwrap_or(default.post_tag),
+            }
+        }),
+        explain: search_query.explain.unwrap_or(false),
+        mode: search_query.mode.unwrap_or_default(),
+        rerank: search_query.rerank.unwrap_or(false),
+    };
 
     let selected_sources = HashSet::from([
         SourceName::PravneOsebe,
@@ -108,51 +345,395 @@ async fn search(
         SourceName::PoslovniRegisterSlovenije,
     ]);
 
-    match search::search_indexes(
+    let search_response = search::search_indexes(
         &indexes,
         &readers,
-        selected_sources,
+        selected_sources.clone(),
         query.clone(),
-        maybe_limit,
+        options.clone(),
     )
-    .await
-    {
-        Ok(res) => Ok(Json(results_to_json(res))),
+    .await;
+    match search_response {
+        Ok(res) => {
+            let mut search_result = results_to_json(res, &links);
+            if search_result.results.len() < SPARSE_HITS {
+                search_result.suggestions = spellcheck::suggest(
+                    &indexes,
+                    &readers,
+                    &selected_sources,
+                    &query,
+                    &options,
+                    search_query.suggestions.unwrap_or(3),
+                )
+                .await
+                .unwrap_or_else(|err| {
+                    error!("Failed to suggest corrections for {:#?}: {}", query, err);
+                    vec![]
+                });
+            }
+            Ok(Json(search_result))
+        }
         Err(err) => {
             error!(
                 "Failed to search indexes: {} with {:#?}. Returning empty response",
                 err, query
             );
-            Ok(Json(SearchResult { results: vec![] }))
+            Ok(Json(SearchResult {
+                results: vec![],
+                facets: FacetCounts::new(),
+                debug: HashMap::new(),
+                suggestions: vec![],
+            }))
+        }
+    }
+}
+
+async fn search_batch(
+    state: axum::extract::State<AppState>,
+    Json(batch_request): Json<BatchRequest>,
+) -> axum::response::Result<impl IntoResponse> {
+    let AppState {
+        indexes,
+        readers,
+        links,
+        max_batch_size,
+        ..
+    } = state.0;
+
+    if batch_request.queries.len() > max_batch_size {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "Batch of {} queries is over the maximum of {}",
+                batch_request.queries.len(),
+                max_batch_size
+            ),
+        )
+            .into());
+    }
+
+    let mut search_tasks = JoinSet::new();
+    let mut positions = HashMap::new();
+    for (position, batch_query) in batch_request.queries.into_iter().enumerate() {
+        let Some(selected_sources) = parse_sources(batch_query.sources.iter().map(String::as_str))
+        else {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Unknown source in query {}", position),
+            )
+                .into());
+        };
+        // Structured queries are matched as plain text, so a name can not break the parser.
+        let query = match (&batch_query.query, &batch_query.name) {
+            (Some(query), _) => query.clone(),
+            (None, Some(name)) => search::plain_query(&format!(
+                "{} {}",
+                name,
+                batch_query.address.as_deref().unwrap_or_default()
+            )),
+            (None, None) => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("Query {} has neither a query nor a name", position),
+                )
+                    .into());
+            }
+        };
+        let options = SearchOptions {
+            limit: batch_query.limit,
+            legal_form: batch_query.legal_form,
+            postcode: batch_query.postcode,
+            city: batch_query.city,
+            vat_registered: batch_query.vat_registered,
+            registered_after: batch_query.registered_after,
+            registered_before: batch_query.registered_before,
+            status: batch_query.status,
+            activity: batch_query.activity,
+            mode: batch_query.mode.unwrap_or_default(),
+            rerank: batch_query.rerank.unwrap_or(false),
+            ..SearchOptions::default()
+        };
+
+        // Searching is CPU bound, so it runs on blocking threads and leaves the runtime free
+        // to serve other requests.
+        let sources = search::snapshot_sources(&indexes, &readers, &selected_sources).await;
+        let task = search_tasks.spawn_blocking(move || {
+            search::search_sources(&sources, &query, options).map_err(|err| err.to_string())
+        });
+        positions.insert(task.id(), position);
+    }
+
+    // A query that fails, even by panicking, only fails its own result.
+    let mut batch_results: Vec<(usize, BatchResult)> = Vec::new();
+    while let Some(joined) = search_tasks.join_next_with_id().await {
+        let (position, search_response) = match joined {
+            Ok((id, search_response)) => (positions[&id], search_response),
+            Err(err) => (positions[&err.id()], Err(err.to_string())),
+        };
+        let batch_result = match search_response {
+            Ok(search_response) => BatchResult {
+                results: results_to_json(search_response, &links).results,
+                error: None,
+            },
+            Err(err) => {
+                error!("Failed to search query {} of a batch: {}", position, err);
+                BatchResult {
+                    results: vec![],
+                    error: Some(err),
+                }
+            }
+        };
+        batch_results.push((position, batch_result));
+    }
+
+    // Put the results back in the order of the queries.
+    batch_results.sort_by_key(|(position, _)| *position);
+    Ok(Json(
+        batch_results
+            .into_iter()
+            .map(|(_, batch_result)| batch_result)
+            .collect::<Vec<_>>(),
+    ))
+}
+
+async fn create_job(
+    state: axum::extract::State<AppState>,
+    job_query: Query<JobQuery>,
+    body: Bytes,
+) -> axum::response::Result<impl IntoResponse> {
+    let AppState {
+        jobs_folder,
+        job_sender,
+        ..
+    } = state.0;
+    let Query(job_query) = job_query;
+
+    // The CSV reader splits on a single byte, which only an ASCII character is in UTF-8.
+    let delimiter = job_query.delimiter.unwrap_or(',');
+    if !delimiter.is_ascii() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Delimiter must be a single ASCII character",
+        )
+            .into());
+    }
+
+    let job = jobs::create(
+        &jobs_folder,
+        &body,
+        job_query.format.unwrap_or(JobFormat::Csv),
+        job_query.name_col,
+        job_query.address_col,
+        delimiter,
+        job_query.min_confidence.unwrap_or(0.6),
+    )
+    .map_err(|err| {
+        error!("Failed to create job: {}", err);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to store the upload",
+        )
+    })?;
+    info!("Queued job {} over {} rows", job.id, job.rows);
+    job_sender
+        .send(job.id.clone())
+        .map_err(|_| (StatusCode::SERVICE_UNAVAILABLE, "Job worker is not running"))?;
+
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+async fn job_status(
+    state: axum::extract::State<AppState>,
+    Path(id): Path<String>,
+) -> axum::response::Result<Json<Job>> {
+    jobs::read(&state.jobs_folder, &id)
+        .map(Json)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Unknown job").into())
+}
+
+async fn job_result(
+    state: axum::extract::State<AppState>,
+    Path(id): Path<String>,
+) -> axum::response::Result<impl IntoResponse> {
+    let Some(job) = jobs::read(&state.jobs_folder, &id) else {
+        return Err((StatusCode::NOT_FOUND, "Unknown job").into());
+    };
+    if job.state != JobState::Completed {
+        return Err((StatusCode::CONFLICT, "Job has not completed").into());
+    }
+
+    let result = tokio::fs::read(jobs::result_path(&state.jobs_folder, &job))
+        .await
+        .map_err(|err| {
+            error!("Failed to read the result of job {}: {}", job.id, err);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to read the result",
+            )
+        })?;
+    Ok(([(header::CONTENT_TYPE, job.format.content_type())], result))
+}
+
+async fn autocomplete(
+    state: axum::extract::State<AppState>,
+    autocomplete_query: Query<AutocompleteQuery>,
+) -> axum::response::Result<impl IntoResponse> {
+    let AppState {
+        indexes, readers, ..
+    } = state.0;
+
+    let Some(selected_sources) = parse_sources(split_list(&autocomplete_query.sources)) else {
+        return Err((StatusCode::BAD_REQUEST, "Unknown source").into());
+    };
+
+    let limit = autocomplete_query.limit.unwrap_or(5);
+    match search::autocomplete(
+        &indexes,
+        &readers,
+        selected_sources,
+        &autocomplete_query.q,
+        limit,
+    )
+    .await
+    {
+        Ok(suggestions) => Ok(Json(
+            suggestions
+                .into_iter()
+                .map(AutocompleteResult::from)
+                .collect::<Vec<_>>(),
+        )),
+        Err(err) => {
+            error!(
+                "Failed to autocomplete: {} with {:#?}. Returning empty response",
+                err, autocomplete_query.q
+            );
+            Ok(Json(vec![]))
         }
     }
 }
 
+async fn sources(state: axum::extract::State<AppState>) -> Json<Vec<SourceStats>> {
+    Json(metadata::source_stats(&state.readers, &state.indexes_folder).await)
+}
+
+async fn search_addresses(
+    state: axum::extract::State<AppState>,
+    address_query: Query<AddressQuery>,
+) -> axum::response::Result<Json<Vec<AddressGroup>>> {
+    let Some(selected_sources) = parse_sources(split_list(&address_query.sources)) else {
+        return Err((StatusCode::BAD_REQUEST, "Unknown source").into());
+    };
+
+    match search::search_addresses(
+        &state.indexes,
+        &state.readers,
+        selected_sources,
+        &address_query.q,
+        address_query.limit.unwrap_or(10),
+    )
+    .await
+    {
+        Ok(groups) => Ok(Json(groups)),
+        Err(err) => {
+            error!(
+                "Failed to search addresses: {} with {:#?}. Returning empty response",
+                err, address_query.q
+            );
+            Ok(Json(vec![]))
+        }
+    }
+}
+
+async fn validate_vat_id(
+    state: axum::extract::State<AppState>,
+    Path(id): Path<String>,
+) -> Json<VatIdCheck> {
+    Json(validation::check_vat_id(&state.readers, &id).await)
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct DocumentResult {
     pub source_name: String,
     pub document: Value,
     pub score: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance_km: Option<f64>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub snippets: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub similarity: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+    /// The same sole proprietor in the other source.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub linked: Option<Linked>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct SearchResult {
     pub results: Vec<DocumentResult>,
+    #[serde(skip_serializing_if = "FacetCounts::is_empty")]
+    pub facets: FacetCounts,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub debug: HashMap<String, QueryDebug>,
+    /// Corrected queries that do have hits, when the search had few or none.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub suggestions: Vec<QuerySuggestion>,
 }
 
-fn results_to_json(search_results: SearchResults) -> SearchResult {
+fn results_to_json(search_response: SearchResponse, links: &LinkTable) -> SearchResult {
     let mut results = Vec::new();
-    for (source_name, documents) in search_results {
-        for (score, _, json_document) in documents {
-            if let Ok(doc_value) = serde_json::from_str(&json_document) {
+    for (source_name, documents) in search_response.results {
+        for result in documents {
+            if let Ok(doc_value) = serde_json::from_str(&result.json) {
                 results.push(DocumentResult {
+                    linked: links.linked(source_name, &result.document),
                     source_name: source_name.to_string(),
                     document: doc_value,
-                    score,
+                    score: result.score,
+                    distance_km: result.distance_km,
+                    snippets: result.snippets,
+                    explanation: result.explanation,
+                    similarity: result.similarity,
+                    confidence: result.confidence,
                 });
             }
         }
     }
 
-    SearchResult { results }
+    SearchResult {
+        results,
+        facets: search_response.facets,
+        debug: search_response
+            .debug
+            .into_iter()
+            .map(|(source_name, query_debug)| (source_name.to_string(), query_debug))
+            .collect(),
+        suggestions: vec![],
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AutocompleteResult {
+    pub source_name: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub company_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vat_id: Option<String>,
+    pub score: f32,
+}
+
+impl From<Suggestion> for AutocompleteResult {
+    fn from(suggestion: Suggestion) -> Self {
+        AutocompleteResult {
+            source_name: suggestion.source_name.to_string(),
+            name: suggestion.name,
+            company_id: suggestion.company_id,
+            vat_id: suggestion.vat_id,
+            score: suggestion.score,
+        }
+    }
 }
@@ -11,6 +11,8 @@ pub struct AppConfig {
     pub force_download: bool,
     #[arg(short, long, default_value_t = false)]
     pub reindex: bool,
+    #[arg(long, default_value_t = false)]
+    pub rollback: bool,
     #[arg(short, long)]
     pub query: Option<String>,
 }
@@ -22,4 +24,13 @@ pub struct ServerConfig {
     pub port: usize,
     #[arg(long, default_value = "0.0.0.0")]
     pub host: String,
+    /// Most queries accepted in one `POST /search/batch` request
+    #[arg(long, env, default_value_t = 100)]
+    pub max_batch_size: usize,
+    /// Where bulk matching jobs keep their uploads and results
+    #[arg(long, env, default_value = "jobs")]
+    pub jobs_folder: String,
+    /// Largest file accepted by `POST /jobs`, in megabytes
+    #[arg(long, env, default_value_t = 256)]
+    pub max_upload_mb: usize,
 }
@@ -0,0 +1,27 @@
+use tantivy::TantivyDocument;
+use tantivy::schema::{Field, NamedFieldDocument, OwnedValue, Schema, Value};
+
+/// The first value of `field` if it is text that is not empty.
+pub fn text(document: &TantivyDocument, field: Field) -> Option<&str> {
+    document
+        .get_first(field)
+        .and_then(|value| value.as_str())
+        .filter(|value| !value.is_empty())
+}
+
+/// [`text`] of the field named `field_name`, if the schema has one.
+pub fn stored_text<'a>(
+    document: &'a TantivyDocument,
+    schema: &Schema,
+    field_name: &str,
+) -> Option<&'a str> {
+    text(document, schema.get_field(field_name).ok()?)
+}
+
+/// [`text`] of a document as returned with a search result.
+pub fn named_text<'a>(document: &'a NamedFieldDocument, field_name: &str) -> Option<&'a str> {
+    match document.0.get(field_name)?.first()? {
+        OwnedValue::Str(value) if !value.is_empty() => Some(value),
+        _ => None,
+    }
+}
@@ -0,0 +1,106 @@
+use crate::tpconfig::{
+    AUTOCOMPLETE_FIELDS, FACET_DIMENSIONS, FACETS_FIELD, PHONETIC_FIELDS, PHONETIC_SUFFIX,
+    PREFIX_SUFFIX,
+};
+use crate::{address, documents, legal_form, postcodes, skd};
+use tantivy::TantivyDocument;
+use tantivy::schema::{Facet, Schema};
+
+/// Fills the [`crate::tpconfig::Position::Derived`] fields of a document from the raw
+/// fields read out of the source file. Derivations only run for schemas that have both
+/// the input and the output fields.
+pub fn enrich(schema: &Schema, document: &mut TantivyDocument) {
+    if let (Ok(full_name), Ok(company_name), Ok(legal_form)) = (
+        schema.get_field("full_name"),
+        schema.get_field("company_name"),
+        schema.get_field("legal_form"),
+    ) && let Some(value) = documents::text(document, full_name).map(String::from)
+    {
+        let (base_name, maybe_legal_form) = legal_form::split_legal_form(&value);
+        document.add_text(company_name, base_name);
+        if let Some(normalized) = maybe_legal_form {
+            document.add_text(legal_form, normalized);
+        }
+    }
+
+    // The registered name is used where there is one, so the legal form can be typed too.
+    for field_name in AUTOCOMPLETE_FIELDS {
+        if let Ok(prefix_field) = schema.get_field(&format!("{}{}", field_name, PREFIX_SUFFIX))
+            && let Some(value) = ["full_name", field_name]
+                .into_iter()
+                .filter_map(|source| schema.get_field(source).ok())
+                .find_map(|field| documents::text(document, field).map(String::from))
+        {
+            document.add_text(prefix_field, value);
+        }
+    }
+
+    for field_name in PHONETIC_FIELDS {
+        if let (Ok(field), Ok(phonetic_field)) = (
+            schema.get_field(field_name),
+            schema.get_field(&format!("{}{}", field_name, PHONETIC_SUFFIX)),
+        ) && let Some(value) = documents::text(document, field).map(String::from)
+        {
+            document.add_text(phonetic_field, value);
+        }
+    }
+
+    if let Ok(address_field) = schema.get_field("address")
+        && let Some(value) = documents::text(document, address_field).map(String::from)
+    {
+        let parsed = address::parse(&value);
+        let key = parsed.key();
+        for (field_name, part) in [
+            ("street", parsed.street),
+            ("house_number", parsed.house_number),
+            ("postcode", parsed.postcode),
+            ("city", parsed.city),
+        ] {
+            if let (Ok(field), Some(part)) = (schema.get_field(field_name), part) {
+                document.add_text(field, part);
+            }
+        }
+        if let (Ok(field), Some(key)) = (schema.get_field("address_key"), key) {
+            document.add_text(field, key);
+        }
+    }
+
+    if let Some(location) =
+        documents::stored_text(document, schema, "postcode").and_then(postcodes::lookup)
+    {
+        if let Ok(field) = schema.get_field("municipality") {
+            document.add_text(field, &location.municipality);
+        }
+        if let Ok(field) = schema.get_field("region") {
+            document.add_text(field, &location.region);
+        }
+        if let (Ok(lat), Ok(lon)) = (schema.get_field("lat"), schema.get_field("lon")) {
+            document.add_f64(lat, location.lat);
+            document.add_f64(lon, location.lon);
+        }
+    }
+
+    let activity_code = documents::stored_text(document, schema, "activity_code").map(String::from);
+    if let (Ok(field), Some(entry)) = (
+        schema.get_field("activity"),
+        activity_code.as_deref().and_then(skd::lookup),
+    ) {
+        document.add_text(field, &entry.label);
+    }
+
+    if let Ok(facets) = schema.get_field(FACETS_FIELD) {
+        for dimension in FACET_DIMENSIONS {
+            if let Some(value) =
+                documents::stored_text(document, schema, dimension).map(String::from)
+            {
+                document.add_facet(facets, Facet::from_path([dimension, value.as_str()]));
+            }
+        }
+        if let Some(path) = activity_code.as_deref().and_then(skd::path) {
+            document.add_facet(
+                facets,
+                Facet::from_path(["skd".to_string()].into_iter().chain(path)),
+            );
+        }
+    }
+}
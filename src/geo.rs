@@ -0,0 +1,70 @@
+use crate::postcodes;
+
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min_lat: f64,
+    pub min_lon: f64,
+    pub max_lat: f64,
+    pub max_lon: f64,
+}
+
+impl BoundingBox {
+    /// Smallest box that contains every point within `radius_km` of `center`.
+    pub fn around(center: Point, radius_km: f64) -> BoundingBox {
+        let lat_delta = (radius_km / EARTH_RADIUS_KM).to_degrees();
+        let lon_delta = lat_delta / center.lat.to_radians().cos().max(f64::EPSILON);
+        BoundingBox {
+            min_lat: center.lat - lat_delta,
+            min_lon: center.lon - lon_delta,
+            max_lat: center.lat + lat_delta,
+            max_lon: center.lon + lon_delta,
+        }
+    }
+}
+
+/// Great-circle distance between two points in kilometers.
+pub fn distance_km(a: Point, b: Point) -> f64 {
+    let (lat_a, lat_b) = (a.lat.to_radians(), b.lat.to_radians());
+    let half_chord = ((lat_b - lat_a) / 2.0).sin().powi(2)
+        + lat_a.cos() * lat_b.cos() * ((b.lon - a.lon).to_radians() / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * half_chord.sqrt().asin()
+}
+
+fn parse_numbers<const N: usize>(raw: &str) -> Option<[f64; N]> {
+    let numbers: Vec<f64> = raw
+        .split(',')
+        .map(|part| part.trim().parse::<f64>())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    numbers.try_into().ok()
+}
+
+/// Parses `lat,lon` or the name of a post office or municipality, e.g. "Maribor".
+pub fn parse_point(raw: &str) -> Option<Point> {
+    if let Some([lat, lon]) = parse_numbers(raw) {
+        return Some(Point { lat, lon });
+    }
+    postcodes::find_place(raw).map(|info| Point {
+        lat: info.lat,
+        lon: info.lon,
+    })
+}
+
+/// Parses `min_lat,min_lon,max_lat,max_lon`.
+pub fn parse_bounding_box(raw: &str) -> Option<BoundingBox> {
+    let [min_lat, min_lon, max_lat, max_lon] = parse_numbers(raw)?;
+    Some(BoundingBox {
+        min_lat,
+        min_lon,
+        max_lat,
+        max_lon,
+    })
+}
@@ -1,23 +1,33 @@
 use crate::config::AppConfig;
+use crate::metadata::{self, IndexMetadata, RowCounts};
 use crate::search::ReaderMap;
 use crate::tpconfig::*;
-use crate::{search, tpconfig};
+use crate::{
+    documents, enrich, legal_form, postcodes, search, skd, tokenizers, tpconfig, validation,
+};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
 use csv::ReaderBuilder;
 use encoding_rs::WINDOWS_1252;
 use io::Error;
-use log::info;
+use log::{info, warn};
 use std::collections::HashMap;
 use std::io::prelude::*;
 use std::io::{BufRead, BufReader, Cursor};
-use std::ops::Deref;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::{fs, io};
-use tantivy::Index;
 use tantivy::directory::MmapDirectory;
 use tantivy::doc;
+use tantivy::schema::{Field, FieldType, Schema};
+use tantivy::{DateTime, Index, TantivyDocument};
 use tokio::sync::Mutex;
 
+const STAGING_SUFFIX: &str = "staging";
+const PREVIOUS_SUFFIX: &str = "previous";
+/// Generation directories are named `<index path>.gen<number>`, the live index and the
+/// previous one are symlinks to them.
+const GENERATION_PREFIX: &str = "gen";
+
 fn read_by_name_from_zip(
     zip_path: PathBuf,
     file_path: &str,
@@ -64,7 +74,7 @@ fn read_first_csv_from_zip(
     }
 }
 
-fn slice_line(input: &String, position: (usize, usize)) -> String {
+fn slice_line(input: &str, position: (usize, usize)) -> String {
     let (start, end) = position;
     input
         .chars()
@@ -75,136 +85,619 @@ fn slice_line(input: &String, position: (usize, usize)) -> String {
         .to_string()
 }
 
+/// Reads dates as the sources write them, e.g. "2001-05-14" or "14.05.2001".
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    ["%Y-%m-%d", "%d.%m.%Y"]
+        .into_iter()
+        .find_map(|format| NaiveDate::parse_from_str(value.trim(), format).ok())
+}
+
+/// Midnight UTC of `date`, as dates are indexed.
+pub fn date_time(date: NaiveDate) -> DateTime {
+    DateTime::from_timestamp_secs(date.and_time(NaiveTime::MIN).and_utc().timestamp())
+}
+
+/// Adds a value read out of a source file converted to the type of the field. Flag columns
+/// are marked with a character and otherwise left blank, and blank or unreadable dates are
+/// left out. Only the code is kept of an activity column, which may hold more.
+fn add_raw_value(schema: &Schema, document: &mut TantivyDocument, field: Field, value: &str) {
+    if schema.get_field_name(field) == "activity_code" {
+        if let Some(code) = skd::find_code(value) {
+            document.add_text(field, code);
+        }
+        return;
+    }
+    match schema.get_field_entry(field).field_type() {
+        FieldType::Bool(_) => document.add_bool(field, !value.is_empty()),
+        FieldType::Date(_) => {
+            if let Some(date) = parse_date(value) {
+                document.add_date(field, date_time(date));
+            }
+        }
+        _ => document.add_field_value(field, value),
+    }
+}
+
+/// Counts the tax numbers and company ids of a document that fail their check digit and
+/// the postcodes missing from the bundled postcode table.
+fn count_issues(schema: &Schema, document: &TantivyDocument, counts: &mut RowCounts) {
+    if let Some(vat_id) = documents::stored_text(document, schema, "vat_id")
+        && !validation::is_valid_vat_id(vat_id)
+    {
+        counts.add_invalid_vat_id(vat_id);
+    }
+    if let Some(company_id) = documents::stored_text(document, schema, "company_id")
+        && !validation::is_valid_company_id(company_id)
+    {
+        counts.add_invalid_company_id(company_id);
+    }
+    if documents::stored_text(document, schema, "postcode")
+        .is_some_and(|postcode| postcodes::lookup(postcode).is_none())
+    {
+        counts.unmatched_postcodes += 1;
+    }
+}
+
 fn index_zipped_csv_fixed_positions(
     source_config: &SourceConfig,
     index: &Index,
     path: PathBuf,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+) -> Result<RowCounts, Box<dyn std::error::Error + Send + Sync>> {
     info!("Indexing {}", source_config.name);
-    let (_, fields) = (source_config.schema)().unwrap();
+    let (schema, fields) = (source_config.schema)().unwrap();
 
     if let Some(zip_file_path) = source_config.zip_file_path {
         let reader = BufReader::new(read_by_name_from_zip(path, zip_file_path)?);
 
         let mut index_writer = index.writer(100_000_000)?;
 
-        let mut rows = 0;
+        let mut counts = RowCounts::default();
         for line in reader.lines() {
-            if let Ok(line) = line {
-                let mut document = doc! {};
-                for (field, position) in fields {
-                    let Position::Fixed(start, stop) = position else {
-                        panic!()
-                    };
-                    let value = slice_line(&line, (start.clone(), stop.clone()));
-                    document.add_field_value(*field, value.deref());
-                }
-
-                index_writer
-                    .add_document(document)
-                    .expect("Failed to add document");
-                rows += 1;
+            let Ok(line) = line else {
+                counts.skipped_rows += 1;
+                continue;
+            };
+            let mut document = doc! {};
+            for (field, position) in fields {
+                let (start, stop) = match position {
+                    Position::Fixed(start, stop) => (*start, *stop),
+                    Position::Derived => continue,
+                    Position::Index(_) | Position::Column(_) => panic!(),
+                };
+                let value = slice_line(&line, (start, stop));
+                add_raw_value(schema, &mut document, *field, &value);
             }
+            enrich::enrich(schema, &mut document);
+            count_issues(schema, &document, &mut counts);
+
+            index_writer
+                .add_document(document)
+                .expect("Failed to add document");
+            counts.rows += 1;
         }
 
         index_writer.commit()?;
-        info!("Indexed {} for {}", rows, source_config.name);
+        info!("Indexed {} for {}", counts.rows, source_config.name);
 
-        Ok(())
+        Ok(counts)
     } else {
-        Ok(())
+        Ok(RowCounts::default())
     }
 }
 
+/// Spelling of a column header that ignores case, carons and how words are separated, so
+/// "DATUM_VPISA", "Datum vpisa" and "datum-vpisa" are the same column.
+fn header_key(header: &str) -> String {
+    header
+        .trim()
+        .trim_start_matches('\u{feff}')
+        .chars()
+        .map(|c| match legal_form::fold(c) {
+            ' ' | '-' => '_',
+            folded => folded,
+        })
+        .collect()
+}
+
 fn index_zipped_csv_with_header(
     source_config: &SourceConfig,
     index: &Index,
     path: PathBuf,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+) -> Result<RowCounts, Box<dyn std::error::Error + Send + Sync>> {
     info!("Indexing {}", source_config.name);
 
-    let (_, fields) = (source_config.schema)().unwrap();
+    let (schema, fields) = (source_config.schema)().unwrap();
     let reader = read_first_csv_from_zip(path)?;
     let mut csv_reader = ReaderBuilder::new().has_headers(true).from_reader(reader);
+    let headers: Vec<String> = csv_reader.headers()?.iter().map(String::from).collect();
+
+    // Named columns are looked up once, so a column missing from the file is reported
+    // instead of quietly leaving its field empty in every document.
+    let mut columns: Vec<(Field, usize)> = Vec::new();
+    let mut counts = RowCounts::default();
+    for (field, position) in fields {
+        match position {
+            Position::Index(position_index) => columns.push((*field, *position_index)),
+            Position::Column(column) => match headers
+                .iter()
+                .position(|header| header_key(header) == header_key(column))
+            {
+                Some(column_index) => columns.push((*field, column_index)),
+                None => {
+                    warn!(
+                        "Column {} is missing from the {} file, {} is left empty",
+                        column,
+                        source_config.name,
+                        schema.get_field_name(*field)
+                    );
+                    counts.missing_columns.push(column.to_string());
+                }
+            },
+            Position::Derived => {}
+            Position::Fixed(_, _) => panic!(),
+        }
+    }
+    counts.missing_columns.sort();
 
     let mut index_writer = index.writer(100_000_000)?;
-    for (_, result) in csv_reader.records().enumerate() {
-        if let Ok(record) = result {
-            let csv_fields: Vec<String> = record.iter().map(String::from).collect();
-            let mut document = doc! {};
+    for record in csv_reader.records() {
+        let Ok(record) = record else {
+            counts.skipped_rows += 1;
+            continue;
+        };
+        let mut document = doc! {};
 
-            for (field, position) in fields {
-                let Position::Index(position_index) = position else {
-                    panic!()
-                };
-                let value = csv_fields.get(position_index.clone()).unwrap();
-                document.add_field_value(*field, value.deref());
+        for (field, column_index) in &columns {
+            if let Some(value) = record.get(*column_index) {
+                add_raw_value(schema, &mut document, *field, value);
             }
-
-            index_writer.add_document(document)?;
         }
+        enrich::enrich(schema, &mut document);
+        count_issues(schema, &document, &mut counts);
+
+        index_writer.add_document(document)?;
+        counts.rows += 1;
     }
     index_writer.commit()?;
+    info!("Indexed {} for {}", counts.rows, source_config.name);
+
+    Ok(counts)
+}
+
+/// Reads the modification date of the data file inside the downloaded archive, which is
+/// the closest thing to a snapshot date the upstream sources publish.
+fn archive_file_date(zip_path: &Path, file_path: Option<&str>) -> Option<NaiveDateTime> {
+    let zip_file = fs::File::open(zip_path).ok()?;
+    let mut archive = zip::ZipArchive::new(zip_file).ok()?;
+    let file_name = match file_path {
+        Some(file_path) => file_path.to_string(),
+        None => archive
+            .file_names()
+            .find(|name| name.ends_with(".csv"))?
+            .to_string(),
+    };
+    let modified = archive.by_name(&file_name).ok()?.last_modified()?;
+
+    NaiveDate::from_ymd_opt(
+        modified.year().into(),
+        modified.month().into(),
+        modified.day().into(),
+    )?
+    .and_hms_opt(
+        modified.hour().into(),
+        modified.minute().into(),
+        modified.second().into(),
+    )
+}
+
+pub fn live_index_path(indexes_folder: &Path, source_config: &SourceConfig) -> PathBuf {
+    indexes_folder.join(source_config.index_path.unwrap_or("unknown"))
+}
+
+fn generation_path(indexes_folder: &Path, source_config: &SourceConfig, suffix: &str) -> PathBuf {
+    let index_path = source_config.index_path.unwrap_or("unknown");
+    indexes_folder.join(format!("{}.{}", index_path, suffix))
+}
+
+/// Checks that a freshly built index contains every row that was written into it.
+fn validate_index(
+    source_config: &SourceConfig,
+    index: &Index,
+    rows: usize,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if rows == 0 {
+        return Err(Box::new(Error::new(
+            io::ErrorKind::InvalidData,
+            format!("No rows were indexed for {}", source_config.name),
+        )));
+    }
+
+    let num_docs = index.reader()?.searcher().num_docs();
+    if num_docs != rows as u64 {
+        return Err(Box::new(Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Index for {} has {} documents, expected {}",
+                source_config.name, num_docs, rows
+            ),
+        )));
+    }
+
+    Ok(())
+}
+
+/// Name of the generation directory numbered `generation`, e.g. "pravne_osebe.gen3".
+fn generation_name(source_config: &SourceConfig, generation: u64) -> String {
+    format!(
+        "{}.{}{}",
+        source_config.index_path.unwrap_or("unknown"),
+        GENERATION_PREFIX,
+        generation
+    )
+}
+
+/// Numbers of the generation directories of an index, in no particular order.
+fn generations(
+    indexes_folder: &Path,
+    source_config: &SourceConfig,
+) -> Result<Vec<u64>, Box<dyn std::error::Error + Send + Sync>> {
+    let prefix = format!(
+        "{}.{}",
+        source_config.index_path.unwrap_or("unknown"),
+        GENERATION_PREFIX
+    );
+    Ok(fs::read_dir(indexes_folder)?
+        .filter_map(|entry| {
+            entry
+                .ok()?
+                .file_name()
+                .to_str()?
+                .strip_prefix(&prefix)?
+                .parse()
+                .ok()
+        })
+        .collect())
+}
+
+/// Moves `directory` to a new generation directory and returns its name.
+fn new_generation(
+    indexes_folder: &Path,
+    source_config: &SourceConfig,
+    directory: &Path,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let generation = generations(indexes_folder, source_config)?
+        .into_iter()
+        .max()
+        .map_or(1, |generation| generation + 1);
+    let name = generation_name(source_config, generation);
+    fs::rename(directory, indexes_folder.join(&name))?;
+    Ok(name)
+}
+
+/// Points the symlink at `link` to the generation directory `target` by renaming a new
+/// link over it, so there is a readable index at `link` at all times.
+fn point_link(link: &Path, target: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut next_link = link.as_os_str().to_owned();
+    next_link.push(".next");
+    let next_link = PathBuf::from(next_link);
+    if next_link.symlink_metadata().is_ok() {
+        fs::remove_file(&next_link)?;
+    }
+    std::os::unix::fs::symlink(target, &next_link)?;
+    fs::rename(&next_link, link)?;
+    Ok(())
+}
+
+/// The generation directory `link` points to. Indexes from before generations were kept
+/// have a directory in place of the link, which is moved to a generation of its own. Until
+/// the link replaces it there is no index at `link`, which only happens once.
+fn link_target(
+    indexes_folder: &Path,
+    source_config: &SourceConfig,
+    link: &Path,
+) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let Ok(link_metadata) = link.symlink_metadata() else {
+        return Ok(None);
+    };
+    if link_metadata.is_symlink() {
+        return Ok(fs::read_link(link)?.to_str().map(String::from));
+    }
+    let target = new_generation(indexes_folder, source_config, link)?;
+    std::os::unix::fs::symlink(&target, link)?;
+    Ok(Some(target))
+}
+
+/// Removes the generation directories neither the live nor the previous index points to.
+fn remove_unused_generations(
+    indexes_folder: &Path,
+    source_config: &SourceConfig,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let live_path = live_index_path(indexes_folder, source_config);
+    let previous_path = generation_path(indexes_folder, source_config, PREVIOUS_SUFFIX);
+    let used: Vec<PathBuf> = [live_path, previous_path]
+        .iter()
+        .filter_map(|link| fs::read_link(link).ok())
+        .collect();
+    for generation in generations(indexes_folder, source_config)? {
+        let name = generation_name(source_config, generation);
+        if !used.contains(&PathBuf::from(&name)) {
+            fs::remove_dir_all(indexes_folder.join(name))?;
+        }
+    }
+    Ok(())
+}
+
+/// Swaps the staging generation in as the live index. The live index is a symlink to a
+/// generation directory, which is switched over in one rename, and the generation it
+/// pointed to is kept as the previous one so it can be restored with [`rollback_index`].
+fn promote_index(
+    indexes_folder: &Path,
+    source_config: &SourceConfig,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let live_path = live_index_path(indexes_folder, source_config);
+    let staging_path = generation_path(indexes_folder, source_config, STAGING_SUFFIX);
+    let previous_path = generation_path(indexes_folder, source_config, PREVIOUS_SUFFIX);
+
+    let live_target = link_target(indexes_folder, source_config, &live_path)?;
+    // Only a link can be renamed over, so a previous directory becomes a generation too.
+    link_target(indexes_folder, source_config, &previous_path)?;
+    let staging_target = new_generation(indexes_folder, source_config, &staging_path)?;
+    point_link(&live_path, &staging_target)?;
+    if let Some(live_target) = live_target {
+        point_link(&previous_path, &live_target)?;
+    }
+    remove_unused_generations(indexes_folder, source_config)?;
+
+    info!(
+        "Promoted index for {} to {}",
+        source_config.name,
+        indexes_folder.join(staging_target).display()
+    );
+
+    Ok(())
+}
+
+/// Swaps the live and the previous generation, so rolling back twice restores the index
+/// that was live before.
+pub fn rollback_index(
+    indexes_folder: &Path,
+    source_config: &SourceConfig,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let live_path = live_index_path(indexes_folder, source_config);
+    let previous_path = generation_path(indexes_folder, source_config, PREVIOUS_SUFFIX);
+
+    let Some(previous_target) = link_target(indexes_folder, source_config, &previous_path)? else {
+        return Err(Box::new(Error::new(
+            io::ErrorKind::NotFound,
+            format!("No previous index generation for {}", source_config.name),
+        )));
+    };
+
+    let live_target = link_target(indexes_folder, source_config, &live_path)?;
+    point_link(&live_path, &previous_target)?;
+    match live_target {
+        Some(live_target) => point_link(&previous_path, &live_target)?,
+        None => fs::remove_file(&previous_path)?,
+    }
+
+    info!(
+        "Rolled back index for {} to the previous generation",
+        source_config.name
+    );
 
     Ok(())
 }
 
+/// Builds a new generation of the index for `source_name` into a staging directory and
+/// promotes it once it has been validated. The live index is left untouched on failure.
 pub async fn index_source(
     source_name: SourceName,
-    maybe_index: Option<Index>,
+    indexes_folder: PathBuf,
     path: PathBuf,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+) -> Result<Index, Box<dyn std::error::Error + Send + Sync>> {
     let source_config = CONFIG
         .iter()
         .find(|config| config.name == source_name)
         .unwrap();
 
-    match (source_config.kind, maybe_index) {
-        (SourceKind::PravneOsebe | SourceKind::FizicneOsebe, Some(index)) => {
-            index_zipped_csv_fixed_positions(source_config, &index, path)
+    build_index(source_config, &indexes_folder, path)
+}
+
+fn build_index(
+    source_config: &SourceConfig,
+    indexes_folder: &Path,
+    path: PathBuf,
+) -> Result<Index, Box<dyn std::error::Error + Send + Sync>> {
+    let Some((schema, _)) = (source_config.schema)() else {
+        return Err(Box::new(Error::other(format!(
+            "Functionality not yet implemented for {}",
+            source_config.kind
+        ))));
+    };
+
+    let staging_path = generation_path(indexes_folder, source_config, STAGING_SUFFIX);
+    if staging_path.exists() {
+        fs::remove_dir_all(&staging_path)?;
+    }
+    fs::create_dir_all(&staging_path)?;
+
+    let staging_index = Index::create_in_dir(&staging_path, schema.clone())?;
+    tokenizers::register(&staging_index);
+    let indexed = match source_config.kind {
+        SourceKind::PravneOsebe | SourceKind::FizicneOsebe => {
+            index_zipped_csv_fixed_positions(source_config, &staging_index, path.clone())
+        }
+        SourceKind::PoslovniRegisterSlovenije => {
+            index_zipped_csv_with_header(source_config, &staging_index, path.clone())
+        }
+        source_kind => Err(format!("Functionality not yet implemented for {}", source_kind).into()),
+    }
+    .and_then(|counts| {
+        validate_index(source_config, &staging_index, counts.rows)?;
+        if counts.invalid_vat_ids > 0 {
+            warn!(
+                "{} rows of {} have a tax number with a wrong check digit",
+                counts.invalid_vat_ids, source_config.name
+            );
+        }
+        if counts.invalid_company_ids > 0 {
+            warn!(
+                "{} rows of {} have a company id with a wrong check digit",
+                counts.invalid_company_ids, source_config.name
+            );
+        }
+        if !counts.missing_columns.is_empty() {
+            warn!(
+                "{} was indexed without the columns {}",
+                source_config.name,
+                counts.missing_columns.join(", ")
+            );
         }
-        (SourceKind::PoslovniRegisterSlovenije, Some(index)) => {
-            index_zipped_csv_with_header(source_config, &index, path)
+        if counts.unmatched_postcodes > 0 {
+            warn!(
+                "{} rows of {} have a postcode missing from the postcode table",
+                counts.unmatched_postcodes, source_config.name
+            );
+        }
+        let upstream_file_date = archive_file_date(&path, source_config.zip_file_path);
+        let index_metadata = IndexMetadata::new(source_config, &path, upstream_file_date, counts)?;
+        metadata::write(&staging_path, &index_metadata)
+    });
+    drop(staging_index);
+
+    if let Err(e) = indexed {
+        fs::remove_dir_all(&staging_path)?;
+        return Err(e);
+    }
+
+    promote_index(indexes_folder, source_config)?;
+
+    let index = Index::open_in_dir(live_index_path(indexes_folder, source_config))?;
+    tokenizers::register(&index);
+    Ok(index)
+}
+
+/// Whether the index has no documents, as the ones created on a first start before any
+/// reindex or for a source whose download failed.
+fn is_empty_index(index_path: &Path) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(Index::open_in_dir(index_path)?
+        .searchable_segment_metas()?
+        .iter()
+        .all(|segment_meta| segment_meta.num_docs() == 0))
+}
+
+/// Explains why the index on disk can not be opened by this release, if it can not. Indexes
+/// built before metadata was recorded are treated as schema version 0, unless they are
+/// empty and never had any.
+fn schema_mismatch(
+    source_config: &SourceConfig,
+    schema: &Schema,
+    index_path: &Path,
+) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+    if !index_path.exists() || !Index::exists(&MmapDirectory::open(index_path)?)? {
+        return Ok(None);
+    }
+
+    let schema_version = match metadata::read(index_path) {
+        Some(index_metadata) => index_metadata.schema_version,
+        None if is_empty_index(index_path)? => source_config.schema_version,
+        None => 0,
+    };
+    if schema_version != source_config.schema_version {
+        return Ok(Some(format!(
+            "built with schema version {}, this release uses version {}",
+            schema_version, source_config.schema_version
+        )));
+    }
+
+    if Index::open_in_dir(index_path)?.schema() != *schema {
+        return Ok(Some(
+            "fields on disk differ from the configured schema".to_string(),
+        ));
+    }
+
+    Ok(None)
+}
+
+/// Brings an outdated index up to the current schema. It is rebuilt from the stored raw
+/// archive when there is one, moved aside when a reindex was requested anyway, and
+/// otherwise refused so that the server does not start with indexes it can not read.
+fn migrate_index(
+    config: &AppConfig,
+    source_config: &SourceConfig,
+    indexes_folder: &Path,
+    reason: String,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let archive_path =
+        PathBuf::from(&config.storage_folder).join(source_config.data_path.unwrap_or("unknown"));
+    let index_path = live_index_path(indexes_folder, source_config);
+
+    if is_empty_index(&index_path)? {
+        warn!(
+            "Empty index for {} is outdated ({}). Creating it again.",
+            source_config.name, reason
+        );
+        fs::remove_dir_all(&index_path)?;
+        Ok(())
+    } else if archive_path.exists() {
+        warn!(
+            "Index for {} is outdated ({}). Rebuilding it from {}.",
+            source_config.name,
+            reason,
+            archive_path.display()
+        );
+        build_index(source_config, indexes_folder, archive_path)?;
+        Ok(())
+    } else if config.reindex {
+        warn!(
+            "Index for {} is outdated ({}). Moving it aside until the reindex finishes.",
+            source_config.name, reason
+        );
+        let previous_path = generation_path(indexes_folder, source_config, PREVIOUS_SUFFIX);
+        if previous_path.exists() {
+            fs::remove_dir_all(&previous_path)?;
         }
-        (source_kind, _) => Err(Box::new(Error::new(
-            io::ErrorKind::Other,
-            format!("Functionality not yet implemented for {}", source_kind),
-        ))),
+        fs::rename(&index_path, previous_path)?;
+        Ok(())
+    } else {
+        Err(format!(
+            "Index for {} at {} is outdated ({}) and no raw archive was found at {}. \
+             Run `fuzzija --reindex` to download the data and rebuild the index.",
+            source_config.name,
+            index_path.display(),
+            reason,
+            archive_path.display()
+        )
+        .into())
     }
 }
 
 pub type IndexMap = HashMap<SourceName, Index>;
 pub fn open_or_create_indexes(
     config: &AppConfig,
-    indexes_folder: &PathBuf,
+    indexes_folder: &Path,
 ) -> Result<IndexMap, Box<dyn std::error::Error + Send + Sync>> {
     let mut indexes: IndexMap = HashMap::new();
 
-    if config.reindex {
-        if indexes_folder.exists() {
-            fs::remove_dir_all(indexes_folder)?;
-            info!(
-                "Deleted existing indexes folder at {}",
-                indexes_folder.display()
-            );
+    for (_, source_config) in tpconfig::available_sources() {
+        if config.rollback
+            && let Err(e) = rollback_index(indexes_folder, source_config)
+        {
+            warn!("Rollback skipped for {}: {}", source_config.name, e);
         }
-    }
 
-    for (_, source_config) in tpconfig::available_sources() {
         if let Some((schema, _)) = (source_config.schema)() {
             info!(
                 "Creating or opening index for {} in {}/{}",
                 source_config.name,
                 indexes_folder.display(),
-                source_config.index_path.as_deref().unwrap_or("unknown")
+                source_config.index_path.unwrap_or("unknown")
             );
 
-            let index_path_raw = source_config
-                .index_path
-                .or_else(|| Some("unknown"))
-                .unwrap();
-
-            let index_path = indexes_folder.join(index_path_raw);
+            let index_path = live_index_path(indexes_folder, source_config);
+            if let Some(reason) = schema_mismatch(source_config, schema, &index_path)? {
+                migrate_index(config, source_config, indexes_folder, reason)?;
+            }
             if !index_path.exists() {
                 fs::create_dir_all(&index_path)?;
             }
@@ -214,6 +707,7 @@ pub fn open_or_create_indexes(
             // let index = Index::create_in_ram(schema.clone());
 
             let index = Index::open_or_create(directory, schema.clone())?;
+            tokenizers::register(&index);
 
             indexes.insert(source_config.name, index);
         }
@@ -241,10 +735,10 @@ pub async fn init(
     app_config: &AppConfig,
 ) -> Result<(Arc<Mutex<IndexMap>>, Arc<Mutex<ReaderMap>>), Box<dyn std::error::Error + Send + Sync>>
 {
-    let (_, indexes_folders) = create_directories(&app_config)?;
+    let (_, indexes_folders) = create_directories(app_config)?;
 
     let index_map = Arc::new(Mutex::new(open_or_create_indexes(
-        &app_config,
+        app_config,
         &indexes_folders,
     )?));
 
@@ -252,3 +746,331 @@ pub async fn init(
 
     Ok((index_map, reader_map))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tantivy::DocAddress;
+    use tantivy::schema::Value;
+    use tempfile::TempDir;
+
+    fn source_config() -> &'static SourceConfig {
+        &CONFIG[0]
+    }
+
+    /// Stages a generation holding a file that tells it apart from the others.
+    fn stage(indexes_folder: &Path, marker: &str) {
+        let staging_path = generation_path(indexes_folder, source_config(), STAGING_SUFFIX);
+        fs::create_dir_all(&staging_path).unwrap();
+        fs::write(staging_path.join("marker"), marker).unwrap();
+    }
+
+    fn marker(path: &Path) -> String {
+        fs::read_to_string(path.join("marker")).unwrap()
+    }
+
+    fn live(indexes_folder: &Path) -> String {
+        marker(&live_index_path(indexes_folder, source_config()))
+    }
+
+    fn previous(indexes_folder: &Path) -> String {
+        marker(&generation_path(
+            indexes_folder,
+            source_config(),
+            PREVIOUS_SUFFIX,
+        ))
+    }
+
+    #[test]
+    fn promotes_the_first_generation() {
+        let folder = TempDir::new().unwrap();
+        stage(folder.path(), "first");
+        promote_index(folder.path(), source_config()).unwrap();
+
+        assert_eq!(live(folder.path()), "first");
+        assert!(
+            live_index_path(folder.path(), source_config())
+                .symlink_metadata()
+                .unwrap()
+                .is_symlink()
+        );
+        assert!(!generation_path(folder.path(), source_config(), STAGING_SUFFIX).exists());
+        assert!(!generation_path(folder.path(), source_config(), PREVIOUS_SUFFIX).exists());
+    }
+
+    #[test]
+    fn keeps_the_live_generation_as_the_previous_one() {
+        let folder = TempDir::new().unwrap();
+        for generation in ["first", "second", "third"] {
+            stage(folder.path(), generation);
+            promote_index(folder.path(), source_config()).unwrap();
+        }
+
+        assert_eq!(live(folder.path()), "third");
+        assert_eq!(previous(folder.path()), "second");
+        assert_eq!(
+            generations(folder.path(), source_config()).unwrap().len(),
+            2
+        );
+    }
+
+    #[test]
+    fn moves_a_live_directory_into_a_generation() {
+        let folder = TempDir::new().unwrap();
+        let live_path = live_index_path(folder.path(), source_config());
+        fs::create_dir_all(&live_path).unwrap();
+        fs::write(live_path.join("marker"), "directory").unwrap();
+        stage(folder.path(), "staged");
+        promote_index(folder.path(), source_config()).unwrap();
+
+        assert_eq!(live(folder.path()), "staged");
+        assert_eq!(previous(folder.path()), "directory");
+    }
+
+    #[test]
+    fn replaces_a_previous_directory() {
+        let folder = TempDir::new().unwrap();
+        for (suffix, marker) in [("", "live"), (".previous", "previous")] {
+            let path =
+                folder
+                    .path()
+                    .join(format!("{}{}", source_config().index_path.unwrap(), suffix));
+            fs::create_dir_all(&path).unwrap();
+            fs::write(path.join("marker"), marker).unwrap();
+        }
+        stage(folder.path(), "staged");
+        promote_index(folder.path(), source_config()).unwrap();
+
+        assert_eq!(live(folder.path()), "staged");
+        assert_eq!(previous(folder.path()), "live");
+        assert_eq!(
+            generations(folder.path(), source_config()).unwrap().len(),
+            2
+        );
+    }
+
+    #[test]
+    fn rolls_back_and_forth() {
+        let folder = TempDir::new().unwrap();
+        for generation in ["first", "second"] {
+            stage(folder.path(), generation);
+            promote_index(folder.path(), source_config()).unwrap();
+        }
+
+        rollback_index(folder.path(), source_config()).unwrap();
+        assert_eq!(live(folder.path()), "first");
+        assert_eq!(previous(folder.path()), "second");
+
+        rollback_index(folder.path(), source_config()).unwrap();
+        assert_eq!(live(folder.path()), "second");
+        assert_eq!(previous(folder.path()), "first");
+    }
+
+    #[test]
+    fn refuses_to_roll_back_without_a_previous_generation() {
+        let folder = TempDir::new().unwrap();
+        assert!(rollback_index(folder.path(), source_config()).is_err());
+
+        stage(folder.path(), "first");
+        promote_index(folder.path(), source_config()).unwrap();
+        assert!(rollback_index(folder.path(), source_config()).is_err());
+        assert_eq!(live(folder.path()), "first");
+    }
+
+    /// Zips `contents` as the registries publish them, the tax registry in UTF-8 and the
+    /// business register in Windows-1252.
+    fn zip_fixture(folder: &Path, file_name: &str, contents: &[u8]) -> PathBuf {
+        let path = folder.join("fixture.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        zip.start_file(file_name, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(contents).unwrap();
+        zip.finish().unwrap();
+        path
+    }
+
+    fn business_register() -> &'static SourceConfig {
+        CONFIG
+            .iter()
+            .find(|c| c.kind == SourceKind::PoslovniRegisterSlovenije)
+            .unwrap()
+    }
+
+    #[test]
+    fn matches_headers_however_they_are_written() {
+        assert_eq!(header_key("Datum vpisa"), header_key("DATUM_VPISA"));
+        assert_eq!(header_key("\u{feff}Naslov "), header_key("NASLOV"));
+        assert_eq!(header_key("Matična-številka"), "maticna_stevilka");
+    }
+
+    #[test]
+    fn reads_business_register_columns_by_header() {
+        let folder = TempDir::new().unwrap();
+        let path = zip_fixture(
+            folder.path(),
+            "fixture.csv",
+            &WINDOWS_1252
+                .encode(
+                    "Matična številka,Naziv,Naslov,Datum vpisa,Datum izbrisa,Status,SKD\n\
+                     5025796000,PETROL d.d.,\"Dunajska c. 50, 1000 Ljubljana\",15.01.1991,,A,47.300\n",
+                )
+                .0,
+        );
+        let source_config = business_register();
+        let (schema, _) = (source_config.schema)().unwrap();
+        let index = Index::create_in_ram(schema.clone());
+        tokenizers::register(&index);
+
+        let counts = index_zipped_csv_with_header(source_config, &index, path).unwrap();
+        assert_eq!(counts.rows, 1);
+        assert!(counts.missing_columns.is_empty());
+
+        let searcher = index.reader().unwrap().searcher();
+        let document: TantivyDocument = searcher.doc(DocAddress::new(0, 0)).unwrap();
+        let text = |field_name| documents::stored_text(&document, schema, field_name);
+        let date = |field_name| {
+            document
+                .get_first(schema.get_field(field_name).unwrap())
+                .and_then(|value| value.as_datetime())
+        };
+        assert_eq!(text("address"), Some("Dunajska c. 50, 1000 Ljubljana"));
+        assert_eq!(text("postcode"), Some("1000"));
+        assert_eq!(text("status"), Some("A"));
+        assert_eq!(text("activity_code"), Some("47.300"));
+        assert_eq!(
+            date("registered_at"),
+            Some(date_time(NaiveDate::from_ymd_opt(1991, 1, 15).unwrap()))
+        );
+        assert_eq!(date("deregistered_at"), None);
+    }
+
+    #[test]
+    fn records_the_columns_missing_from_the_file() {
+        let folder = TempDir::new().unwrap();
+        let path = zip_fixture(
+            folder.path(),
+            "fixture.csv",
+            &WINDOWS_1252.encode(
+                "MATICNA,NAZIV,ADRESA\n5025796000,PETROL d.d.,\"Dunajska c. 50, 1000 Ljubljana\"\n",
+            )
+            .0,
+        );
+        let source_config = business_register();
+        let (schema, _) = (source_config.schema)().unwrap();
+        let index = Index::create_in_ram(schema.clone());
+        tokenizers::register(&index);
+
+        let counts = index_zipped_csv_with_header(source_config, &index, path).unwrap();
+        assert_eq!(
+            counts.missing_columns,
+            ["DATUM_IZBRISA", "DATUM_VPISA", "NASLOV", "SKD", "STATUS"]
+        );
+    }
+
+    #[test]
+    fn parses_dates_in_both_formats() {
+        let date = NaiveDate::from_ymd_opt(1991, 1, 15);
+        assert_eq!(parse_date("1991-01-15"), date);
+        assert_eq!(parse_date("15.01.1991"), date);
+        assert_eq!(parse_date(" 15.1.1991 "), date);
+        assert_eq!(parse_date(""), None);
+        assert_eq!(parse_date("31.02.1991"), None);
+        assert_eq!(parse_date("15/01/1991"), None);
+    }
+
+    #[test]
+    fn filters_registration_dates_including_the_boundaries() {
+        let folder = TempDir::new().unwrap();
+        let path = zip_fixture(
+            folder.path(),
+            "fixture.csv",
+            &WINDOWS_1252
+                .encode(
+                    "MATICNA,NAZIV,NASLOV,DATUM_VPISA,DATUM_IZBRISA,STATUS,SKD\n\
+                     5300231,GRADNJE ENA d.o.o.,,31.12.1999,,A,\n\
+                     5025796,GRADNJE DVE d.o.o.,,01.01.2000,,A,\n\
+                     5043611,GRADNJE TRI d.o.o.,,2010-12-31,,A,\n\
+                     5860571,GRADNJE STIRI d.o.o.,,2011-01-01,,A,\n",
+                )
+                .0,
+        );
+        let source_config = business_register();
+        let (schema, _) = (source_config.schema)().unwrap();
+        let index = Index::create_in_ram(schema.clone());
+        tokenizers::register(&index);
+        index_zipped_csv_with_header(source_config, &index, path).unwrap();
+        let sources = [(source_config.name, index.clone(), index.reader().unwrap())];
+
+        let names = |registered_after, registered_before| {
+            let options = search::SearchOptions {
+                registered_after: NaiveDate::from_ymd_opt(2000, 1, 1).filter(|_| registered_after),
+                registered_before: NaiveDate::from_ymd_opt(2010, 12, 31)
+                    .filter(|_| registered_before),
+                ..search::SearchOptions::default()
+            };
+            let mut names: Vec<String> = search::search_sources(&sources, "gradnje", options)
+                .unwrap()
+                .results
+                .into_values()
+                .flatten()
+                .filter_map(|result| {
+                    documents::named_text(&result.document, "full_name").map(String::from)
+                })
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            names(true, true),
+            ["GRADNJE DVE d.o.o.", "GRADNJE TRI d.o.o."]
+        );
+        assert_eq!(
+            names(true, false),
+            [
+                "GRADNJE DVE d.o.o.",
+                "GRADNJE STIRI d.o.o.",
+                "GRADNJE TRI d.o.o."
+            ]
+        );
+        assert_eq!(
+            names(false, true),
+            [
+                "GRADNJE DVE d.o.o.",
+                "GRADNJE ENA d.o.o.",
+                "GRADNJE TRI d.o.o."
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_only_the_code_of_the_activity_slice() {
+        let folder = TempDir::new().unwrap();
+        let source_config = &CONFIG[0];
+        let line = format!(
+            "{:<2}{:<2}{:<9}{:<11}{:<18}{:<101}{}\n",
+            "*",
+            "1",
+            "10000046",
+            "5300231000",
+            "52.421    2",
+            "OPALAB d.o.o.",
+            "Tržaška cesta 12a, 1000 Ljubljana"
+        );
+        let path = zip_fixture(
+            folder.path(),
+            source_config.zip_file_path.unwrap(),
+            line.as_bytes(),
+        );
+        let (schema, _) = (source_config.schema)().unwrap();
+        let index = Index::create_in_ram(schema.clone());
+        tokenizers::register(&index);
+        index_zipped_csv_fixed_positions(source_config, &index, path).unwrap();
+
+        let searcher = index.reader().unwrap().searcher();
+        let document: TantivyDocument = searcher.doc(DocAddress::new(0, 0)).unwrap();
+        let text = |field_name| documents::stored_text(&document, schema, field_name);
+        assert_eq!(text("activity_code"), Some("52.421"));
+        assert!(text("activity").is_some());
+        assert_eq!(text("full_name"), Some("OPALAB d.o.o."));
+    }
+}
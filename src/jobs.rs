@@ -0,0 +1,275 @@
+use crate::indexer::IndexMap;
+use crate::reconcile::{self, ReconcileConfig, ReconcileSummary};
+use crate::search::{self, ReaderMap};
+use crate::tpconfig::SourceName;
+use chrono::{DateTime, Utc};
+use csv::ReaderBuilder;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tantivy::{Index, IndexReader};
+use tokio::sync::Mutex;
+use tokio::sync::mpsc::{self, UnboundedSender};
+
+const JOB_FILE: &str = "job.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobFormat {
+    Csv,
+    Jsonl,
+}
+
+impl JobFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            JobFormat::Csv => "csv",
+            JobFormat::Jsonl => "jsonl",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            JobFormat::Csv => "text/csv",
+            JobFormat::Jsonl => "application/x-ndjson",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+}
+
+/// A bulk reconciliation job, persisted as `job.json` next to its input and result.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Job {
+    pub id: String,
+    pub state: JobState,
+    pub format: JobFormat,
+    pub name_column: String,
+    pub address_column: Option<String>,
+    pub delimiter: char,
+    pub min_confidence: f64,
+    /// Rows of the input, not counting a header.
+    pub rows: usize,
+    pub progress: ReconcileSummary,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Job ids are hex digests, anything else is not looked up on disk.
+fn is_job_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn job_path(jobs_folder: &Path, id: &str) -> PathBuf {
+    jobs_folder.join(id)
+}
+
+fn input_path(jobs_folder: &Path, job: &Job) -> PathBuf {
+    job_path(jobs_folder, &job.id).join(format!("input.{}", job.format.extension()))
+}
+
+pub fn result_path(jobs_folder: &Path, job: &Job) -> PathBuf {
+    job_path(jobs_folder, &job.id).join(format!("result.{}", job.format.extension()))
+}
+
+pub fn write(
+    jobs_folder: &Path,
+    job: &Job,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let json = serde_json::to_string_pretty(job)?;
+    // Written aside and renamed, so a job is never read half written.
+    let path = job_path(jobs_folder, &job.id);
+    fs::write(path.join(format!("{}.tmp", JOB_FILE)), json)?;
+    fs::rename(path.join(format!("{}.tmp", JOB_FILE)), path.join(JOB_FILE))?;
+    Ok(())
+}
+
+pub fn read(jobs_folder: &Path, id: &str) -> Option<Job> {
+    if !is_job_id(id) {
+        return None;
+    }
+    let json = fs::read_to_string(job_path(jobs_folder, id).join(JOB_FILE)).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+/// Counts CSV records rather than lines, as a quoted field may span several lines.
+fn count_rows(input: &[u8], format: JobFormat, delimiter: u8) -> usize {
+    match format {
+        JobFormat::Csv => ReaderBuilder::new()
+            .delimiter(delimiter)
+            .flexible(true)
+            .from_reader(input)
+            .records()
+            .count(),
+        JobFormat::Jsonl => input
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.trim_ascii().is_empty())
+            .count(),
+    }
+}
+
+/// Stores the uploaded `input` as a new queued job.
+pub fn create(
+    jobs_folder: &Path,
+    input: &[u8],
+    format: JobFormat,
+    name_column: String,
+    address_column: Option<String>,
+    delimiter: char,
+    min_confidence: f64,
+) -> Result<Job, Box<dyn std::error::Error + Send + Sync>> {
+    let created_at = Utc::now();
+    let mut hasher = Sha256::new();
+    hasher.update(input);
+    hasher.update(created_at.to_rfc3339().as_bytes());
+    let id = format!("{:x}", hasher.finalize())[..16].to_string();
+
+    let job = Job {
+        id,
+        state: JobState::Queued,
+        format,
+        name_column,
+        address_column,
+        delimiter,
+        min_confidence,
+        rows: count_rows(input, format, u8::try_from(delimiter)?),
+        progress: ReconcileSummary::default(),
+        error: None,
+        created_at,
+        updated_at: created_at,
+    };
+    fs::create_dir_all(job_path(jobs_folder, &job.id))?;
+    fs::write(input_path(jobs_folder, &job), input)?;
+    write(jobs_folder, &job)?;
+    Ok(job)
+}
+
+/// Jobs that were queued or running when the server stopped, oldest first.
+pub fn unfinished(jobs_folder: &Path) -> Vec<Job> {
+    let Ok(entries) = fs::read_dir(jobs_folder) else {
+        return vec![];
+    };
+    let mut jobs: Vec<Job> = entries
+        .filter_map(|entry| read(jobs_folder, &entry.ok()?.file_name().to_string_lossy()))
+        .filter(|job| matches!(job.state, JobState::Queued | JobState::Running))
+        .collect();
+    jobs.sort_by_key(|job| job.created_at);
+    jobs
+}
+
+fn run(
+    sources: &[(SourceName, Index, IndexReader)],
+    jobs_folder: &Path,
+    job: &Job,
+) -> Result<ReconcileSummary, Box<dyn std::error::Error + Send + Sync>> {
+    let config = ReconcileConfig {
+        name_column: job.name_column.clone(),
+        address_column: job.address_column.clone(),
+        delimiter: u8::try_from(job.delimiter)?,
+        min_confidence: job.min_confidence,
+    };
+    let input = File::open(input_path(jobs_folder, job))?;
+    let output = File::create(result_path(jobs_folder, job))?;
+
+    let mut progress_job = job.clone();
+    let mut progress = |summary: &ReconcileSummary| {
+        progress_job.progress = *summary;
+        progress_job.updated_at = Utc::now();
+        if let Err(e) = write(jobs_folder, &progress_job) {
+            warn!(
+                "Failed to record progress of job {}: {}",
+                progress_job.id, e
+            );
+        }
+    };
+    match job.format {
+        JobFormat::Csv => reconcile::reconcile_csv(sources, input, output, &config, &mut progress),
+        JobFormat::Jsonl => reconcile::reconcile_jsonl(
+            sources,
+            BufReader::new(input),
+            output,
+            &config,
+            &mut progress,
+        ),
+    }
+}
+
+/// Starts the worker that runs jobs one at a time in the order they are sent, picking up
+/// the jobs left unfinished by the previous run of the server first. A job that was running
+/// is started over.
+pub fn start_worker(
+    indexes: Arc<Mutex<IndexMap>>,
+    readers: Arc<Mutex<ReaderMap>>,
+    jobs_folder: PathBuf,
+) -> UnboundedSender<String> {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+    for job in unfinished(&jobs_folder) {
+        info!("Resuming job {}", job.id);
+        let _ = sender.send(job.id);
+    }
+
+    tokio::spawn(async move {
+        while let Some(id) = receiver.recv().await {
+            let Some(mut job) = read(&jobs_folder, &id) else {
+                error!("Job {} disappeared before it could run", id);
+                continue;
+            };
+            info!("Running job {} over {} rows", job.id, job.rows);
+            job.state = JobState::Running;
+            job.progress = ReconcileSummary::default();
+            job.updated_at = Utc::now();
+            if let Err(e) = write(&jobs_folder, &job) {
+                error!("Failed to start job {}: {}", job.id, e);
+                continue;
+            }
+
+            // Matching is CPU bound, so the job runs on a blocking thread and leaves the
+            // runtime free to serve searches in the meantime.
+            let sources = search::snapshot_sources(
+                &indexes,
+                &readers,
+                &HashSet::from([
+                    SourceName::PravneOsebe,
+                    SourceName::FizicneOsebe,
+                    SourceName::PoslovniRegisterSlovenije,
+                ]),
+            )
+            .await;
+            let (run_jobs_folder, run_job) = (jobs_folder.clone(), job.clone());
+            let result =
+                tokio::task::spawn_blocking(move || run(&sources, &run_jobs_folder, &run_job))
+                    .await
+                    .unwrap_or_else(|e| Err(e.into()));
+            match result {
+                Ok(summary) => {
+                    job.state = JobState::Completed;
+                    job.progress = summary;
+                }
+                Err(e) => {
+                    error!("Job {} failed: {}", job.id, e);
+                    job.state = JobState::Failed;
+                    job.error = Some(e.to_string());
+                }
+            }
+            job.updated_at = Utc::now();
+            if let Err(e) = write(&jobs_folder, &job) {
+                error!("Failed to record the end of job {}: {}", job.id, e);
+            }
+        }
+    });
+
+    sender
+}
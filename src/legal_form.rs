@@ -0,0 +1,242 @@
+/// Normalized legal forms with the abbreviation letters and the spelled out variants
+/// they are registered under. Longer forms come first so "k.d.d." is not read as "d.d.".
+const LEGAL_FORMS: [(&str, &str, &[&str]); 7] = [
+    ("k.d.d.", "kdd", &["komanditna delniska druzba"]),
+    ("d.o.o.", "doo", &["druzba z omejeno odgovornostjo"]),
+    ("d.n.o.", "dno", &["druzba z neomejeno odgovornostjo"]),
+    ("d.d.", "dd", &["delniska druzba"]),
+    ("k.d.", "kd", &["komanditna druzba"]),
+    (
+        "s.p.",
+        "sp",
+        &["samostojni podjetnik", "samostojna podjetnica"],
+    ),
+    ("z.o.o.", "zoo", &["zadruga z omejeno odgovornostjo"]),
+];
+
+/// Legal forms that lead the name, like "Zavod za kulturo", and are kept as part of it.
+/// Anywhere else the word is just part of the name, as in "Mladinski zavod".
+const LEADING_LEGAL_FORMS: [(&str, &[&str]); 1] = [("zavod", &["zavod"])];
+
+/// Lowercases a character and drops the caron or accent of Slovenian letters.
+pub(crate) fn fold(c: char) -> char {
+    match c.to_lowercase().next().unwrap_or(c) {
+        'č' | 'ć' => 'c',
+        'š' => 's',
+        'ž' => 'z',
+        'đ' => 'd',
+        folded => folded,
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    c.is_whitespace() || c == ',' || c == '-'
+}
+
+/// Whether a legal form that ends at `end` is followed by the end of the name or by the
+/// seat or activity the registries append, as in "Petrol d.d., Ljubljana".
+fn ends_form(folded: &[char], end: usize) -> bool {
+    end == folded.len() || is_separator(folded[end])
+}
+
+/// Returns where the abbreviation that starts at `start` ends, written with or without
+/// dots and spaces between its letters ("d.o.o.", "D. O. O.", "doo"). Without dots it is
+/// only taken at the end of the name, elsewhere "doo" or "sp" may just be a word.
+fn abbreviation_end(folded: &[char], start: usize, letters: &str) -> Option<usize> {
+    let mut end = start;
+    let mut dotted = false;
+    for (position, letter) in letters.chars().enumerate() {
+        if position > 0 {
+            while end < folded.len() && (folded[end] == '.' || folded[end] == ' ') {
+                dotted |= folded[end] == '.';
+                end += 1;
+            }
+        }
+        if end == folded.len() || folded[end] != letter {
+            return None;
+        }
+        end += 1;
+    }
+    if end < folded.len() && folded[end] == '.' {
+        dotted = true;
+        end += 1;
+    }
+
+    let at_end = folded[end..].iter().all(|c| c.is_whitespace() || *c == '.');
+    (ends_form(folded, end) && (dotted || at_end)).then_some(end)
+}
+
+/// Returns where the spelled out `phrase` that starts at `start` ends.
+fn phrase_end(folded: &[char], start: usize, phrase: &str) -> Option<usize> {
+    let phrase: Vec<char> = phrase.chars().collect();
+    let mut end = start + phrase.len();
+    if end > folded.len() || folded[start..end] != phrase[..] {
+        return None;
+    }
+    if end < folded.len() && folded[end] == '.' {
+        end += 1;
+    }
+    ends_form(folded, end).then_some(end)
+}
+
+fn phrase_prefix(folded: &[char], phrase: &str) -> bool {
+    let phrase: Vec<char> = phrase.chars().collect();
+    folded.len() > phrase.len()
+        && folded[..phrase.len()] == phrase[..]
+        && is_separator(folded[phrase.len()])
+}
+
+/// Splits a registered company name into the name itself and its normalized legal form,
+/// e.g. "OPALAB D.O.O." into ("OPALAB", Some("d.o.o.")). The seat or activity that may
+/// follow the form stays in the name, so "Petrol d.d., Ljubljana" becomes
+/// ("Petrol, Ljubljana", Some("d.d.")). Forms that lead the name, like "Zavod za kulturo",
+/// are recognized but kept as part of the name.
+pub fn split_legal_form(name: &str) -> (String, Option<&'static str>) {
+    let chars: Vec<char> = name.trim().chars().collect();
+    let folded: Vec<char> = chars.iter().copied().map(fold).collect();
+
+    // The first form that stands on its own after the start of the name.
+    let found = (1..folded.len())
+        .filter(|start| is_separator(folded[start - 1]))
+        .find_map(|start| {
+            LEGAL_FORMS
+                .iter()
+                .find_map(|(legal_form, letters, phrases)| {
+                    phrases
+                        .iter()
+                        .find_map(|phrase| phrase_end(&folded, start, phrase))
+                        .or_else(|| abbreviation_end(&folded, start, letters))
+                        .map(|end| (start, end, *legal_form))
+                })
+        });
+
+    if let Some((start, end, legal_form)) = found {
+        let base: String = chars[..start].iter().collect();
+        let base = base.trim_end_matches(is_separator);
+        let rest: String = chars[end..].iter().collect();
+        let name = if rest.chars().any(char::is_alphanumeric) {
+            format!("{}{}", base, rest.trim_end())
+        } else {
+            base.to_string()
+        };
+        return (name, Some(legal_form));
+    }
+
+    let legal_form = LEADING_LEGAL_FORMS
+        .iter()
+        .find(|(_, phrases)| phrases.iter().any(|phrase| phrase_prefix(&folded, phrase)))
+        .map(|(legal_form, _)| *legal_form);
+
+    (chars.iter().collect(), legal_form)
+}
+
+/// Maps any spelling of a legal form, as users type it into filters, to its normalized form.
+pub fn normalize(legal_form: &str) -> Option<&'static str> {
+    let compact = |text: &str| -> String {
+        text.chars()
+            .map(fold)
+            .filter(|c| *c != '.' && !c.is_whitespace())
+            .collect()
+    };
+    let typed = compact(legal_form);
+    LEGAL_FORMS
+        .iter()
+        .map(|(normalized, letters, phrases)| (*normalized, *letters, *phrases))
+        .chain(
+            LEADING_LEGAL_FORMS
+                .iter()
+                .map(|(normalized, phrases)| (*normalized, "", *phrases)),
+        )
+        .find(|(_, letters, phrases)| {
+            (!letters.is_empty() && typed == *letters)
+                || phrases.iter().any(|phrase| typed == compact(phrase))
+        })
+        .map(|(normalized, _, _)| normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_a_trailing_legal_form() {
+        assert_eq!(
+            split_legal_form("OPALAB D.O.O."),
+            ("OPALAB".to_string(), Some("d.o.o."))
+        );
+        assert_eq!(
+            split_legal_form("x k.d.d."),
+            ("x".to_string(), Some("k.d.d."))
+        );
+    }
+
+    #[test]
+    fn splits_a_legal_form_followed_by_a_seat_or_activity() {
+        assert_eq!(
+            split_legal_form("Petrol d.d., Ljubljana"),
+            ("Petrol, Ljubljana".to_string(), Some("d.d."))
+        );
+        assert_eq!(
+            split_legal_form("ABC d.o.o. Ljubljana"),
+            ("ABC Ljubljana".to_string(), Some("d.o.o."))
+        );
+        assert_eq!(
+            split_legal_form("Novak s.p. - Gradnje"),
+            ("Novak - Gradnje".to_string(), Some("s.p."))
+        );
+        assert_eq!(
+            split_legal_form("JANEZ NOVAK s.p., gradbena dela"),
+            ("JANEZ NOVAK, gradbena dela".to_string(), Some("s.p."))
+        );
+    }
+
+    #[test]
+    fn takes_undotted_abbreviations_only_at_the_end() {
+        assert_eq!(
+            split_legal_form("OPALAB DOO"),
+            ("OPALAB".to_string(), Some("d.o.o."))
+        );
+        assert_eq!(
+            split_legal_form("SP Trgovina Kranj"),
+            ("SP Trgovina Kranj".to_string(), None)
+        );
+        assert_eq!(
+            split_legal_form("Kava doo bar"),
+            ("Kava doo bar".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn keeps_a_trailing_zavod_in_the_name() {
+        assert_eq!(
+            split_legal_form("Mladinski zavod"),
+            ("Mladinski zavod".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn keeps_a_leading_legal_form_in_the_name() {
+        assert_eq!(
+            split_legal_form("Zavod za kulturo"),
+            ("Zavod za kulturo".to_string(), Some("zavod"))
+        );
+    }
+
+    #[test]
+    fn leaves_names_without_a_legal_form_alone() {
+        assert_eq!(
+            split_legal_form("NOVAK JANEZ"),
+            ("NOVAK JANEZ".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn normalizes_typed_legal_forms() {
+        assert_eq!(normalize("D.O.O."), Some("d.o.o."));
+        assert_eq!(normalize("doo"), Some("d.o.o."));
+        assert_eq!(normalize("Družba z omejeno odgovornostjo"), Some("d.o.o."));
+        assert_eq!(normalize("zavod"), Some("zavod"));
+        assert_eq!(normalize("kdd"), Some("k.d.d."));
+        assert_eq!(normalize("gmbh"), None);
+    }
+}
@@ -1,5 +1,20 @@
+pub mod address;
 pub mod config;
+pub mod documents;
+pub mod enrich;
+pub mod geo;
 pub mod indexer;
+pub mod jobs;
+pub mod legal_form;
+pub mod linking;
+pub mod metadata;
+pub mod postcodes;
+pub mod reconcile;
 pub mod search;
+pub mod similarity;
+pub mod skd;
 pub mod sources;
+pub mod spellcheck;
+pub mod tokenizers;
 pub mod tpconfig;
+pub mod validation;
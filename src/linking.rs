@@ -0,0 +1,352 @@
+use crate::search::ReaderMap;
+use crate::similarity;
+use crate::tpconfig::{self, SourceName};
+use crate::{documents, indexer, metadata};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tantivy::collector::DocSetCollector;
+use tantivy::query::{AllQuery, Query, TermQuery};
+use tantivy::schema::{IndexRecordOption, NamedFieldDocument};
+use tantivy::{IndexReader, TantivyDocument, Term};
+use tokio::sync::Mutex;
+
+const LINKS_FILE: &str = "links.json";
+
+/// A sole proprietor listed as a natural person in the tax registry and as an s.p. in the
+/// business register.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Link {
+    /// Tax number of the person in [`SourceName::FizicneOsebe`].
+    pub vat_id: String,
+    pub person_name: String,
+    /// Company id of the s.p. in [`SourceName::PoslovniRegisterSlovenije`].
+    pub company_id: String,
+    pub company_name: String,
+}
+
+/// Links are kept in a file next to the indexes rather than in the documents of either.
+/// Both registries are downloaded, reindexed and rolled back on their own, so links stored
+/// in the documents would go stale in one index whenever the other changed, or force
+/// reindexing both. The file is built again instead whenever either index is not the one
+/// it was built from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct LinksFile {
+    built_at: DateTime<Utc>,
+    /// When each of the linked indexes was built, to tell whether the links still hold.
+    #[serde(default)]
+    indexed_at: HashMap<String, DateTime<Utc>>,
+    links: Vec<Link>,
+}
+
+/// When the indexes links are built from were built, by source name.
+fn linked_indexes(indexes_folder: &Path) -> HashMap<String, DateTime<Utc>> {
+    tpconfig::available_sources()
+        .into_iter()
+        .filter(|(source_name, _)| {
+            matches!(
+                source_name,
+                SourceName::FizicneOsebe | SourceName::PoslovniRegisterSlovenije
+            )
+        })
+        .filter_map(|(source_name, source_config)| {
+            let index_metadata =
+                metadata::read(&indexer::live_index_path(indexes_folder, source_config))?;
+            Some((source_name.to_string(), index_metadata.indexed_at))
+        })
+        .collect()
+}
+
+/// The counterpart of a hit in the other source.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Linked {
+    pub source_name: String,
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct LinkTable {
+    by_vat_id: HashMap<String, Link>,
+    by_company_id: HashMap<String, Link>,
+}
+
+impl LinkTable {
+    fn new(links: Vec<Link>) -> Self {
+        LinkTable {
+            by_vat_id: links
+                .iter()
+                .map(|link| (link.vat_id.clone(), link.clone()))
+                .collect(),
+            by_company_id: links
+                .into_iter()
+                .map(|link| (link.company_id.clone(), link))
+                .collect(),
+        }
+    }
+
+    /// The counterpart of a hit from `source_name`, if it is a linked sole proprietor.
+    pub fn linked(&self, source_name: SourceName, document: &NamedFieldDocument) -> Option<Linked> {
+        match source_name {
+            SourceName::FizicneOsebe => {
+                let link = self
+                    .by_vat_id
+                    .get(documents::named_text(document, "vat_id")?)?;
+                Some(Linked {
+                    source_name: SourceName::PoslovniRegisterSlovenije.to_string(),
+                    id: link.company_id.clone(),
+                    name: link.company_name.clone(),
+                })
+            }
+            SourceName::PoslovniRegisterSlovenije => {
+                let link = self
+                    .by_company_id
+                    .get(documents::named_text(document, "company_id")?)?;
+                Some(Linked {
+                    source_name: SourceName::FizicneOsebe.to_string(),
+                    id: link.vat_id.clone(),
+                    name: link.person_name.clone(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// An entry read out of one of the indexes, with the key of the address it is registered
+/// at, which a sole proprietor usually shares with the person.
+struct Entry {
+    id: String,
+    name: String,
+    address_key: String,
+}
+
+fn entries(
+    reader: &IndexReader,
+    query: &dyn Query,
+    id_field: &str,
+    name_field: &str,
+) -> tantivy::Result<Vec<Entry>> {
+    let searcher = reader.searcher();
+    let schema = searcher.schema();
+    let (Ok(id), Ok(name), Ok(address_key)) = (
+        schema.get_field(id_field),
+        schema.get_field(name_field),
+        schema.get_field("address_key"),
+    ) else {
+        return Ok(vec![]);
+    };
+    let mut entries = Vec::new();
+    for doc_address in searcher.search(query, &DocSetCollector)? {
+        let document: TantivyDocument = searcher.doc(doc_address)?;
+        let text = |field| documents::text(&document, field).map(String::from);
+        if let (Some(id), Some(name), Some(address_key)) = (text(id), text(name), text(address_key))
+        {
+            entries.push(Entry {
+                id,
+                name,
+                address_key,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// Links every s.p. in the business register to the one person in the tax registry that is
+/// registered at the same address and whose every name is part of the name of the s.p.,
+/// e.g. "NOVAK JANEZ" to "JANEZ NOVAK s.p., gradbena dela". An s.p. with more than one such
+/// person is left unlinked. The two registries share no id, the tax registry only has
+/// the davčna številka and the business register only the matična številka.
+pub async fn build(
+    readers: &Arc<Mutex<ReaderMap>>,
+) -> Result<Vec<Link>, Box<dyn std::error::Error + Send + Sync>> {
+    let (persons, companies) = {
+        let readers_map = readers.lock().await;
+        match (
+            readers_map.get(&SourceName::FizicneOsebe),
+            readers_map.get(&SourceName::PoslovniRegisterSlovenije),
+        ) {
+            (Some(persons), Some(companies)) => (persons.clone(), companies.clone()),
+            _ => return Ok(vec![]),
+        }
+    };
+
+    let Ok(legal_form) = companies.searcher().schema().get_field("legal_form") else {
+        return Ok(vec![]);
+    };
+    let sole_proprietors = TermQuery::new(
+        Term::from_field_text(legal_form, "s.p."),
+        IndexRecordOption::Basic,
+    );
+    let mut persons_by_address: HashMap<String, Vec<Entry>> = HashMap::new();
+    for person in entries(&persons, &AllQuery, "vat_id", "name")? {
+        persons_by_address
+            .entry(person.address_key.clone())
+            .or_default()
+            .push(person);
+    }
+
+    let mut links = Vec::new();
+    for company in entries(&companies, &sole_proprietors, "company_id", "full_name")? {
+        let company_words: HashSet<String> = similarity::words(&company.name).into_iter().collect();
+        let candidates: Vec<&Entry> = persons_by_address
+            .get(&company.address_key)
+            .into_iter()
+            .flatten()
+            .filter(|person| {
+                let person_words = similarity::words(&person.name);
+                !person_words.is_empty()
+                    && person_words.iter().all(|word| company_words.contains(word))
+            })
+            .collect();
+        if let [person] = candidates.as_slice() {
+            links.push(Link {
+                vat_id: person.id.clone(),
+                person_name: person.name.clone(),
+                company_id: company.id,
+                company_name: company.name,
+            });
+        }
+    }
+
+    info!("Linked {} sole proprietors", links.len());
+    Ok(links)
+}
+
+pub fn write(
+    indexes_folder: &Path,
+    links: Vec<Link>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let json = serde_json::to_string_pretty(&LinksFile {
+        built_at: Utc::now(),
+        indexed_at: linked_indexes(indexes_folder),
+        links,
+    })?;
+    fs::write(indexes_folder.join(LINKS_FILE), json)?;
+    Ok(())
+}
+
+/// The links, unless one of the indexes was rebuilt or rolled back since they were built.
+fn read(indexes_folder: &Path) -> Option<Vec<Link>> {
+    let json = fs::read_to_string(indexes_folder.join(LINKS_FILE)).ok()?;
+    let links_file: LinksFile = serde_json::from_str(&json).ok()?;
+    (links_file.indexed_at == linked_indexes(indexes_folder)).then_some(links_file.links)
+}
+
+/// Builds the links again and stores them next to the indexes.
+pub async fn rebuild(
+    readers: &Arc<Mutex<ReaderMap>>,
+    indexes_folder: &Path,
+) -> Result<LinkTable, Box<dyn std::error::Error + Send + Sync>> {
+    let links = build(readers).await?;
+    write(indexes_folder, links.clone())?;
+    Ok(LinkTable::new(links))
+}
+
+/// Reads the links stored next to the indexes, building them first when there are none yet
+/// or the indexes changed since.
+pub async fn load(readers: &Arc<Mutex<ReaderMap>>, indexes_folder: &Path) -> LinkTable {
+    if let Some(links) = read(indexes_folder) {
+        return LinkTable::new(links);
+    }
+    match rebuild(readers, indexes_folder).await {
+        Ok(link_table) => link_table,
+        Err(e) => {
+            warn!("Failed to link sole proprietors: {}", e);
+            LinkTable::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{enrich, tokenizers};
+    use tantivy::Index;
+
+    /// An index of `source_name` holding a document per row of raw field values, derived
+    /// fields filled in as when indexing.
+    fn reader(source_name: SourceName, rows: &[&[(&str, &str)]]) -> IndexReader {
+        let source_config = tpconfig::CONFIG
+            .iter()
+            .find(|source_config| source_config.name == source_name)
+            .unwrap();
+        let (schema, _) = (source_config.schema)().unwrap();
+        let index = Index::create_in_ram(schema.clone());
+        tokenizers::register(&index);
+        let mut index_writer = index.writer(15_000_000).unwrap();
+        for row in rows {
+            let mut document = TantivyDocument::default();
+            for (field_name, value) in *row {
+                document.add_text(schema.get_field(field_name).unwrap(), value);
+            }
+            enrich::enrich(schema, &mut document);
+            index_writer.add_document(document).unwrap();
+        }
+        index_writer.commit().unwrap();
+        index.reader().unwrap()
+    }
+
+    #[tokio::test]
+    async fn links_a_sole_proprietor_to_the_one_person_at_its_address() {
+        let persons = reader(
+            SourceName::FizicneOsebe,
+            &[
+                &[
+                    ("vat_id", "10000046"),
+                    ("name", "NOVAK JANEZ"),
+                    ("address", "Partizanska ul. 5, 2000 Maribor"),
+                ],
+                &[
+                    ("vat_id", "12345679"),
+                    ("name", "KOS MARKO"),
+                    ("address", "Tržaška c. 12, 1000 Ljubljana"),
+                ],
+                &[
+                    ("vat_id", "45884595"),
+                    ("name", "MARKO KOS"),
+                    ("address", "Tržaška cesta 12, 1000 Ljubljana"),
+                ],
+            ],
+        );
+        let companies = reader(
+            SourceName::PoslovniRegisterSlovenije,
+            &[
+                &[
+                    ("company_id", "5300231"),
+                    ("full_name", "JANEZ NOVAK s.p., gradbena dela"),
+                    ("address", "PARTIZANSKA ULICA 5, 2000 MARIBOR"),
+                ],
+                &[
+                    ("company_id", "5025796"),
+                    ("full_name", "NOVAK JANEZ d.o.o."),
+                    ("address", "Partizanska ulica 5, 2000 Maribor"),
+                ],
+                &[
+                    ("company_id", "5043611"),
+                    ("full_name", "MARKO KOS s.p."),
+                    ("address", "Tržaška cesta 12, 1000 Ljubljana"),
+                ],
+            ],
+        );
+        let readers = Arc::new(Mutex::new(ReaderMap::from([
+            (SourceName::FizicneOsebe, persons),
+            (SourceName::PoslovniRegisterSlovenije, companies),
+        ])));
+
+        // The d.o.o. is no sole proprietor and the second s.p. fits two persons.
+        assert_eq!(
+            build(&readers).await.unwrap(),
+            [Link {
+                vat_id: "10000046".to_string(),
+                person_name: "NOVAK JANEZ".to_string(),
+                company_id: "5300231".to_string(),
+                company_name: "JANEZ NOVAK s.p., gradbena dela".to_string(),
+            }]
+        );
+    }
+}
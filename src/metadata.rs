@@ -0,0 +1,167 @@
+use crate::indexer;
+use crate::search::ReaderMap;
+use crate::tpconfig::{self, SourceConfig};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+const METADATA_FILE: &str = "fuzzija.json";
+/// How many of the ids failing their check digit are kept in the metadata to follow up on.
+pub const INVALID_ID_SAMPLE_SIZE: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexMetadata {
+    pub source_name: String,
+    pub source_url: String,
+    pub archive_sha256: String,
+    pub downloaded_at: Option<DateTime<Utc>>,
+    pub upstream_file_date: Option<NaiveDateTime>,
+    pub indexed_at: DateTime<Utc>,
+    pub rows: usize,
+    pub skipped_rows: usize,
+    /// Rows whose tax number fails the check digit, most likely typos at the source.
+    #[serde(default)]
+    pub invalid_vat_ids: usize,
+    /// Rows whose company id fails the check digit.
+    #[serde(default)]
+    pub invalid_company_ids: usize,
+    /// The first few of the tax numbers and company ids failing their check digit.
+    #[serde(default)]
+    pub invalid_vat_id_sample: Vec<String>,
+    #[serde(default)]
+    pub invalid_company_id_sample: Vec<String>,
+    /// Rows whose postcode is not in the bundled table, so they have no municipality,
+    /// region or coordinates.
+    #[serde(default)]
+    pub unmatched_postcodes: usize,
+    /// Named columns the source file did not have, whose fields are left empty.
+    #[serde(default)]
+    pub missing_columns: Vec<String>,
+    pub schema_version: u32,
+}
+
+/// What happened to the rows of a source file while it was indexed.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RowCounts {
+    pub rows: usize,
+    pub skipped_rows: usize,
+    pub invalid_vat_ids: usize,
+    pub invalid_company_ids: usize,
+    pub invalid_vat_id_sample: Vec<String>,
+    pub invalid_company_id_sample: Vec<String>,
+    pub unmatched_postcodes: usize,
+    pub missing_columns: Vec<String>,
+}
+
+impl RowCounts {
+    pub fn add_invalid_vat_id(&mut self, vat_id: &str) {
+        self.invalid_vat_ids += 1;
+        if self.invalid_vat_id_sample.len() < INVALID_ID_SAMPLE_SIZE {
+            self.invalid_vat_id_sample.push(vat_id.to_string());
+        }
+    }
+
+    pub fn add_invalid_company_id(&mut self, company_id: &str) {
+        self.invalid_company_ids += 1;
+        if self.invalid_company_id_sample.len() < INVALID_ID_SAMPLE_SIZE {
+            self.invalid_company_id_sample.push(company_id.to_string());
+        }
+    }
+}
+
+impl IndexMetadata {
+    pub fn new(
+        source_config: &SourceConfig,
+        archive_path: &Path,
+        upstream_file_date: Option<NaiveDateTime>,
+        counts: RowCounts,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let downloaded_at = fs::metadata(archive_path)?
+            .modified()
+            .ok()
+            .map(DateTime::<Utc>::from);
+
+        Ok(IndexMetadata {
+            source_name: source_config.name.to_string(),
+            source_url: source_config.source_url.to_string(),
+            archive_sha256: sha256_file(archive_path)?,
+            downloaded_at,
+            upstream_file_date,
+            indexed_at: Utc::now(),
+            rows: counts.rows,
+            skipped_rows: counts.skipped_rows,
+            invalid_vat_ids: counts.invalid_vat_ids,
+            invalid_company_ids: counts.invalid_company_ids,
+            invalid_vat_id_sample: counts.invalid_vat_id_sample,
+            invalid_company_id_sample: counts.invalid_company_id_sample,
+            unmatched_postcodes: counts.unmatched_postcodes,
+            missing_columns: counts.missing_columns,
+            schema_version: source_config.schema_version,
+        })
+    }
+}
+
+pub fn sha256_file(path: &Path) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+pub fn write(
+    index_path: &Path,
+    metadata: &IndexMetadata,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let json = serde_json::to_string_pretty(metadata)?;
+    fs::write(index_path.join(METADATA_FILE), json)?;
+    Ok(())
+}
+
+pub fn read(index_path: &Path) -> Option<IndexMetadata> {
+    let json = fs::read_to_string(index_path.join(METADATA_FILE)).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceStats {
+    pub source_name: String,
+    pub index_path: String,
+    pub documents: u64,
+    pub metadata: Option<IndexMetadata>,
+}
+
+pub async fn source_stats(
+    readers: &Arc<Mutex<ReaderMap>>,
+    indexes_folder: &Path,
+) -> Vec<SourceStats> {
+    let readers_map = readers.lock().await;
+    tpconfig::available_sources()
+        .into_iter()
+        .map(|(source_name, source_config)| {
+            let index_path = indexer::live_index_path(indexes_folder, source_config);
+            let documents = readers_map
+                .get(&source_name)
+                .map(|reader| reader.searcher().num_docs())
+                .unwrap_or(0);
+
+            SourceStats {
+                source_name: source_name.to_string(),
+                index_path: index_path.display().to_string(),
+                documents,
+                metadata: read(&index_path),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,92 @@
+use csv::ReaderBuilder;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+/// Offline table of Slovenian post offices with the municipality (občina) and statistical
+/// region they belong to and the approximate coordinates of the post office. It lists all
+/// of them, along with the P.O. box postcodes of the larger towns.
+const POSTCODES_CSV: &str = include_str!("../data/postcodes.csv");
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PostcodeInfo {
+    pub postcode: String,
+    pub post_office: String,
+    pub municipality: String,
+    pub region: String,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+static POSTCODES: LazyLock<HashMap<String, PostcodeInfo>> = LazyLock::new(|| {
+    ReaderBuilder::new()
+        .has_headers(true)
+        .from_reader(POSTCODES_CSV.as_bytes())
+        .deserialize::<PostcodeInfo>()
+        .map(|record| record.expect("Bundled postcode table is malformed"))
+        .map(|info| (info.postcode.clone(), info))
+        .collect()
+});
+
+pub fn lookup(postcode: &str) -> Option<&'static PostcodeInfo> {
+    POSTCODES.get(postcode.trim())
+}
+
+/// Returns the municipality or region name as spelled in the table, so filters typed in
+/// any case match the indexed facet values.
+pub fn canonical_name(dimension: &str, value: &str) -> Option<&'static str> {
+    let value = value.trim();
+    POSTCODES.values().find_map(|info| {
+        let name = match dimension {
+            "municipality" => &info.municipality,
+            "region" => &info.region,
+            _ => return None,
+        };
+        name.to_lowercase()
+            .eq(&value.to_lowercase())
+            .then_some(name.as_str())
+    })
+}
+
+/// Finds a post office or municipality by name, ignoring case.
+pub fn find_place(name: &str) -> Option<&'static PostcodeInfo> {
+    let name = name.trim().to_lowercase();
+    let mut candidates: Vec<&PostcodeInfo> = POSTCODES
+        .values()
+        .filter(|info| {
+            info.post_office.to_lowercase() == name || info.municipality.to_lowercase() == name
+        })
+        .collect();
+    // Prefer the main post office, which has the lowest postcode of a municipality.
+    candidates.sort_by(|a, b| a.postcode.cmp(&b.postcode));
+    candidates.first().copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looks_up_the_post_offices_of_small_places() {
+        let info = lookup("4264").unwrap();
+        assert_eq!(info.post_office, "Bohinjska Bistrica");
+        assert_eq!(info.municipality, "Bohinj");
+        assert_eq!(info.region, "Gorenjska");
+        assert_eq!(lookup("9265").unwrap().region, "Pomurska");
+        assert!(lookup("1999").is_none());
+    }
+
+    #[test]
+    fn places_every_post_office_in_slovenia() {
+        for info in POSTCODES.values() {
+            assert!((45.4..=46.9).contains(&info.lat), "{:?}", info);
+            assert!((13.3..=16.7).contains(&info.lon), "{:?}", info);
+        }
+    }
+
+    #[test]
+    fn finds_the_main_post_office_of_a_municipality() {
+        assert_eq!(find_place("ljubljana").unwrap().postcode, "1000");
+        assert_eq!(find_place("Bohinj").unwrap().postcode, "4264");
+    }
+}
@@ -0,0 +1,277 @@
+use crate::search::{self, SearchOptions};
+use crate::tpconfig::SourceName;
+use crate::{address, documents, similarity};
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{BufRead, Read, Write};
+use tantivy::{Index, IndexReader};
+
+/// Candidates fetched from each source for every row.
+const CANDIDATES_PER_SOURCE: usize = 5;
+/// Weight of the name in the confidence of a row with an address, the rest goes to the
+/// address.
+const NAME_WEIGHT: f64 = 0.8;
+/// Rows between two progress reports.
+pub const PROGRESS_INTERVAL: usize = 1000;
+/// A match is ambiguous when another entity comes this close to its confidence.
+const AMBIGUITY_MARGIN: f64 = 0.05;
+/// Columns appended to every row of the output.
+const MATCH_COLUMNS: [&str; 7] = [
+    "match_vat_id",
+    "match_company_id",
+    "match_name",
+    "match_source",
+    "match_score",
+    "match_confidence",
+    "match_ambiguous",
+];
+
+#[derive(Debug, Clone)]
+pub struct ReconcileConfig {
+    pub name_column: String,
+    pub address_column: Option<String>,
+    pub delimiter: u8,
+    /// Best candidates below this confidence are not reported as a match.
+    pub min_confidence: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Match {
+    pub source_name: String,
+    pub vat_id: Option<String>,
+    pub company_id: Option<String>,
+    pub name: Option<String>,
+    /// BM25 score of the hit in its source.
+    pub score: f32,
+    /// Confidence from 0 to 1 that this is the entity the row refers to.
+    pub confidence: f64,
+    /// Another entity matched about as well.
+    pub ambiguous: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReconcileSummary {
+    pub rows: usize,
+    pub matched: usize,
+    pub ambiguous: usize,
+}
+
+/// Whether two matches are the same entity listed in different sources. Company ids are
+/// compared on their first seven digits, as the tax registry appends a business unit to
+/// the matična številka the business register uses.
+fn same_entity(a: &Match, b: &Match) -> bool {
+    if let (Some(a), Some(b)) = (&a.vat_id, &b.vat_id) {
+        return a == b;
+    }
+    if let (Some(a), Some(b)) = (&a.company_id, &b.company_id) {
+        return a.chars().take(7).eq(b.chars().take(7));
+    }
+    a.name
+        .as_deref()
+        .map(str::to_lowercase)
+        .eq(&b.name.as_deref().map(str::to_lowercase))
+}
+
+/// Finds the entity `name` and `address` most likely refer to in `sources`, if anything
+/// matches at all.
+pub fn match_entity(
+    sources: &[(SourceName, Index, IndexReader)],
+    name: &str,
+    address: Option<&str>,
+) -> Result<Option<Match>, Box<dyn std::error::Error + Send + Sync>> {
+    let query = search::plain_query(name);
+    if query.trim().is_empty() {
+        return Ok(None);
+    }
+    let search_response = search::search_sources(
+        sources,
+        &query,
+        SearchOptions {
+            limit: Some(CANDIDATES_PER_SOURCE),
+            rerank: true,
+            ..SearchOptions::default()
+        },
+    )?;
+
+    let address = address.filter(|address| !address.trim().is_empty());
+    let mut candidates: Vec<Match> = search_response
+        .results
+        .into_iter()
+        .flat_map(|(source_name, results)| {
+            results.into_iter().map(move |result| {
+                let name_confidence = result.confidence.unwrap_or_default();
+                let confidence = match (address, documents::named_text(&result.document, "address"))
+                {
+                    (Some(address), Some(candidate_address)) => {
+                        // Street abbreviations are expanded on both sides, as in the index.
+                        let address_similarity = similarity::token_set_similarity(
+                            &address::normalize_street(address),
+                            &address::normalize_street(candidate_address),
+                        );
+                        NAME_WEIGHT * name_confidence + (1.0 - NAME_WEIGHT) * address_similarity
+                    }
+                    _ => name_confidence,
+                };
+                Match {
+                    source_name: source_name.to_string(),
+                    vat_id: documents::named_text(&result.document, "vat_id").map(String::from),
+                    company_id: documents::named_text(&result.document, "company_id")
+                        .map(String::from),
+                    name: documents::named_text(&result.document, "full_name")
+                        .or_else(|| documents::named_text(&result.document, "name"))
+                        .map(String::from),
+                    score: result.score,
+                    confidence,
+                    ambiguous: false,
+                }
+            })
+        })
+        .collect();
+    candidates.sort_by(|a, b| {
+        b.confidence
+            .total_cmp(&a.confidence)
+            .then(b.score.total_cmp(&a.score))
+    });
+
+    let mut candidates = candidates.into_iter();
+    Ok(candidates.next().map(|mut best| {
+        best.ambiguous = candidates.any(|candidate| {
+            best.confidence - candidate.confidence <= AMBIGUITY_MARGIN
+                && !same_entity(&best, &candidate)
+        });
+        best
+    }))
+}
+
+/// Called with the counts so far every [`PROGRESS_INTERVAL`] rows.
+pub type Progress<'a> = &'a mut (dyn FnMut(&ReconcileSummary) + Send);
+
+/// Matches one row and counts it in `summary`. Returns the best match, unless it is below
+/// the minimum confidence.
+fn reconcile_row(
+    sources: &[(SourceName, Index, IndexReader)],
+    name: &str,
+    address: Option<&str>,
+    config: &ReconcileConfig,
+    summary: &mut ReconcileSummary,
+    progress: Progress<'_>,
+) -> Result<Option<Match>, Box<dyn std::error::Error + Send + Sync>> {
+    let best = match_entity(sources, name, address)?
+        .filter(|best| best.confidence >= config.min_confidence);
+
+    summary.rows += 1;
+    if let Some(best) = &best {
+        summary.matched += 1;
+        if best.ambiguous {
+            summary.ambiguous += 1;
+        }
+    }
+    if summary.rows.is_multiple_of(PROGRESS_INTERVAL) {
+        info!("Reconciled {} rows", summary.rows);
+        progress(summary);
+    }
+
+    Ok(best)
+}
+
+/// Matches every row of the `input` CSV and writes it to `output` with the
+/// [`MATCH_COLUMNS`] appended, left empty for rows without a confident match. Matching is
+/// CPU bound, so this is best run on a blocking thread.
+pub fn reconcile_csv<R: Read, W: Write>(
+    sources: &[(SourceName, Index, IndexReader)],
+    input: R,
+    output: W,
+    config: &ReconcileConfig,
+    progress: Progress<'_>,
+) -> Result<ReconcileSummary, Box<dyn std::error::Error + Send + Sync>> {
+    let mut csv_reader = ReaderBuilder::new()
+        .delimiter(config.delimiter)
+        .flexible(true)
+        .from_reader(input);
+    let mut csv_writer = WriterBuilder::new()
+        .delimiter(config.delimiter)
+        .flexible(true)
+        .from_writer(output);
+
+    let headers = csv_reader.headers()?.clone();
+    let column = |column_name: &str| {
+        headers
+            .iter()
+            .position(|header| header.trim().eq_ignore_ascii_case(column_name))
+            .ok_or_else(|| format!("Column {:?} not found in the input", column_name))
+    };
+    let name_column = column(&config.name_column)?;
+    let address_column = config.address_column.as_deref().map(column).transpose()?;
+
+    let mut output_headers = headers.clone();
+    output_headers.extend(MATCH_COLUMNS);
+    csv_writer.write_record(&output_headers)?;
+
+    let mut summary = ReconcileSummary::default();
+    for record in csv_reader.records() {
+        let record = record?;
+        let name = record.get(name_column).unwrap_or_default();
+        let address = address_column.and_then(|address_column| record.get(address_column));
+        let best = reconcile_row(sources, name, address, config, &mut summary, progress)?;
+
+        let mut output_record: StringRecord = record.clone();
+        match best {
+            Some(best) => output_record.extend([
+                best.vat_id.unwrap_or_default(),
+                best.company_id.unwrap_or_default(),
+                best.name.unwrap_or_default(),
+                best.source_name,
+                format!("{:.4}", best.score),
+                format!("{:.4}", best.confidence),
+                best.ambiguous.to_string(),
+            ]),
+            None => output_record.extend([""; MATCH_COLUMNS.len()]),
+        }
+        csv_writer.write_record(&output_record)?;
+    }
+    csv_writer.flush()?;
+
+    Ok(summary)
+}
+
+/// Matches every line of the `input` JSON lines, each an object with the name and address
+/// under the configured keys, and writes it to `output` with a `match` key added, `null`
+/// for lines without a confident match.
+pub fn reconcile_jsonl<R: BufRead, W: Write>(
+    sources: &[(SourceName, Index, IndexReader)],
+    input: R,
+    mut output: W,
+    config: &ReconcileConfig,
+    progress: Progress<'_>,
+) -> Result<ReconcileSummary, Box<dyn std::error::Error + Send + Sync>> {
+    let mut summary = ReconcileSummary::default();
+    for (line_number, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Value::Object(mut object) = serde_json::from_str(&line)? else {
+            return Err(format!("Line {} is not a JSON object", line_number + 1).into());
+        };
+        let text = |key: &str| object.get(key).and_then(Value::as_str).map(String::from);
+        let name = text(&config.name_column).unwrap_or_default();
+        let address = config.address_column.as_deref().and_then(text);
+        let best = reconcile_row(
+            sources,
+            &name,
+            address.as_deref(),
+            config,
+            &mut summary,
+            progress,
+        )?;
+
+        object.insert("match".to_string(), serde_json::to_value(best)?);
+        serde_json::to_writer(&mut output, &object)?;
+        output.write_all(b"\n")?;
+    }
+    output.flush()?;
+
+    Ok(summary)
+}
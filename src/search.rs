@@ -1,12 +1,30 @@
-use crate::indexer::IndexMap;
-use crate::tpconfig::SourceName;
+use crate::geo::{self, BoundingBox, Point};
+use crate::indexer::{self, IndexMap};
+use crate::tokenizers::{EDGE_NGRAM_QUERY_TOKENIZER, MAX_GRAM};
+use crate::tpconfig::{
+    AUTOCOMPLETE_FIELDS, FACET_DIMENSIONS, FACETS_FIELD, NAME_FIELDS, PHONETIC_SUFFIX,
+    PREFIX_SUFFIX, SourceName,
+};
+use crate::validation::{self, Identifier};
+use crate::{address, documents, legal_form, postcodes, similarity, skd};
+use chrono::NaiveDate;
+use clap::ValueEnum;
 use log::info;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::ops::Bound;
 use std::sync::Arc;
-use tantivy::collector::TopDocs;
-use tantivy::query::QueryParser;
+use tantivy::collector::{Count, FacetCollector, TopDocs};
+use tantivy::query::{
+    BooleanQuery, ConstScoreQuery, FuzzyTermQuery, Occur, Query, QueryParser, QueryParserError,
+    RangeQuery, TermQuery,
+};
 use tantivy::schema::*;
-use tantivy::{Document, IndexReader, ReloadPolicy, Score, TantivyDocument};
+use tantivy::snippet::SnippetGenerator;
+use tantivy::{
+    DocAddress, DocId, Document, Index, IndexReader, ReloadPolicy, Score, Searcher, SegmentReader,
+    TantivyDocument, Term,
+};
 use tokio::sync::Mutex;
 
 pub type ReaderMap = HashMap<SourceName, IndexReader>;
@@ -29,49 +47,959 @@ pub async fn open_readers(indexes: &Arc<Mutex<IndexMap>>) -> Arc<Mutex<ReaderMap
     Arc::new(Mutex::new(index_map))
 }
 
-pub type IndexResult = (Score, NamedFieldDocument, String);
+#[derive(Debug)]
+pub struct IndexResult {
+    pub score: Score,
+    pub document: NamedFieldDocument,
+    pub json: String,
+    /// Distance from the `near` point of the search, when one was given.
+    pub distance_km: Option<f64>,
+    /// Fragments of [`HIGHLIGHT_FIELDS`] with the matched parts wrapped in highlight tags.
+    pub snippets: HashMap<String, String>,
+    /// How tantivy arrived at the score, when the search was explained.
+    pub explanation: Option<serde_json::Value>,
+    /// Token set similarity of the name to the query from 0 to 1, in the name match mode.
+    pub similarity: Option<f64>,
+    /// How likely the hit is what was searched for from 0 to 1, when results are reranked.
+    pub confidence: Option<f64>,
+}
+
+/// Fields that snippets with highlighted matches are generated for.
+pub const HIGHLIGHT_FIELDS: [&str; 3] = ["company_name", "name", "address"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Highlight {
+    pub pre_tag: String,
+    pub post_tag: String,
+}
+
+impl Default for Highlight {
+    fn default() -> Self {
+        Highlight {
+            pre_tag: "<b>".to_string(),
+            post_tag: "</b>".to_string(),
+        }
+    }
+}
 pub type SearchResults = HashMap<SourceName, Vec<IndexResult>>;
+/// Hit counts per facet dimension and value, e.g. `{"city": {"MARIBOR": 12}}`.
+pub type FacetCounts = HashMap<String, HashMap<String, u64>>;
+
+#[derive(Debug, Default)]
+pub struct SearchResponse {
+    pub results: SearchResults,
+    pub facets: FacetCounts,
+    /// How the query was understood by each source, when the search was explained.
+    pub debug: HashMap<SourceName, QueryDebug>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QueryDebug {
+    /// The query tree after parsing and applying filters.
+    pub parsed_query: String,
+    /// Tokens the analyzer of each default search field produces for the query string.
+    pub analyzed: HashMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    pub limit: Option<usize>,
+    pub legal_form: Option<String>,
+    pub postcode: Option<String>,
+    pub city: Option<String>,
+    /// Only return taxpayers that are, or are not, registered for VAT.
+    pub vat_registered: Option<bool>,
+    /// Only return entities registered on or after this date.
+    pub registered_after: Option<NaiveDate>,
+    /// Only return entities registered on or before this date.
+    pub registered_before: Option<NaiveDate>,
+    /// Only return entities with this registration status.
+    pub status: Option<String>,
+    /// Only return entities whose SKD activity is in this section, e.g. "F", or under this
+    /// code, e.g. "41" or "41.2".
+    pub activity: Option<String>,
+    /// Drill down filters as facet dimension and value pairs.
+    pub filters: Vec<(String, String)>,
+    /// Facet dimensions to count hits for.
+    pub facets: Vec<String>,
+    pub near: Option<Point>,
+    /// Only return hits within this distance of `near`.
+    pub radius_km: Option<f64>,
+    pub bounding_box: Option<BoundingBox>,
+    pub sort: SortOrder,
+    /// Generate highlighted snippets wrapped in these tags.
+    pub highlight: Option<Highlight>,
+    /// Return score explanations, the parsed query and the analyzer output.
+    pub explain: bool,
+    pub mode: MatchMode,
+    /// Rerank the top hits by [`similarity::match_confidence`] of their name to the query.
+    pub rerank: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Relevance,
+    /// Closest to the `near` point first.
+    Distance,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    /// Match the words as they are written.
+    #[default]
+    Lexical,
+    /// Also match names that sound the same, ranked below the ones written the same.
+    Phonetic,
+    /// Match the words of the name in any order, allowing for typos, and rank by how similar
+    /// the whole name is regardless of word order.
+    Name,
+}
+
+/// Weight of the `*_phonetic` fields relative to the lexical ones in the phonetic mode.
+const PHONETIC_BOOST: Score = 0.5;
+/// Candidates fetched per result in the name mode, as they are ranked again by similarity.
+const NAME_CANDIDATES_PER_RESULT: usize = 5;
+/// Top hits that get reranked, when reranking.
+const RERANK_CANDIDATES: usize = 50;
+/// Candidates less similar than this are dropped in the name mode.
+const MIN_NAME_SIMILARITY: f64 = 0.5;
+
+impl SearchOptions {
+    /// Facet filters with values normalized the way they are indexed.
+    fn filters(&self) -> Vec<(String, String)> {
+        let explicit_filters = [
+            ("legal_form", &self.legal_form),
+            ("postcode", &self.postcode),
+            ("city", &self.city),
+            ("status", &self.status),
+            ("skd", &self.activity),
+        ]
+        .into_iter()
+        .filter_map(|(dimension, value)| Some((dimension.to_string(), value.clone()?)));
+
+        explicit_filters
+            .chain(self.filters.iter().cloned())
+            .map(|(dimension, value)| {
+                let value = normalize_facet_value(&dimension, &value);
+                (dimension, value)
+            })
+            .collect()
+    }
+}
+
+fn normalize_facet_value(dimension: &str, value: &str) -> String {
+    match dimension {
+        "legal_form" => legal_form::normalize(value)
+            .map(String::from)
+            .unwrap_or_else(|| value.trim().to_string()),
+        "city" => address::normalize_city(value),
+        "municipality" | "region" => postcodes::canonical_name(dimension, value)
+            .map(String::from)
+            .unwrap_or_else(|| value.trim().to_string()),
+        _ => value.trim().to_string(),
+    }
+}
+
+/// Query string for text that is not meant as a query, like a name out of a spreadsheet,
+/// with anything the query parser would read as syntax, like quotes, colons or an upper
+/// case `OR`, taken out.
+pub fn plain_query(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_lowercase().next().unwrap_or(c)
+            } else {
+                ' '
+            }
+        })
+        .collect()
+}
+
+/// Parses a `dimension:value` filter as it is passed on the command line or in a URL.
+pub fn parse_filter(raw: &str) -> Option<(String, String)> {
+    let (dimension, value) = raw.split_once(':')?;
+    let (dimension, value) = (dimension.trim(), value.trim());
+    (!dimension.is_empty() && !value.is_empty()).then(|| (dimension.to_string(), value.to_string()))
+}
+
+/// Requires `query` to match without letting it add to the relevance score.
+fn filter_clause(query: Box<dyn Query>) -> (Occur, Box<dyn Query>) {
+    (Occur::Must, Box::new(ConstScoreQuery::new(query, 0.0)))
+}
+
+/// Narrows the parsed query with the filters from `options`. Returns `None` when a filter
+/// can not match anything in this source, like a facet the source does not index.
+fn apply_filters(
+    source_name: SourceName,
+    schema: &Schema,
+    query: Box<dyn Query>,
+    options: &SearchOptions,
+) -> Option<Box<dyn Query>> {
+    let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![(Occur::Must, query)];
+
+    for (dimension, value) in options.filters() {
+        if dimension == "source" {
+            if !source_name.to_string().eq_ignore_ascii_case(&value) {
+                return None;
+            }
+            continue;
+        }
+        if !FACET_DIMENSIONS.contains(&dimension.as_str()) {
+            return None;
+        }
+
+        // Activities are filtered by a code at any level, which matches every code below it.
+        let path = match dimension.as_str() {
+            "skd" => skd::path(&value)?,
+            _ => vec![value],
+        };
+        let field = schema.get_field(FACETS_FIELD).ok()?;
+        clauses.push(filter_clause(Box::new(TermQuery::new(
+            Term::from_facet(
+                field,
+                &Facet::from_path([dimension].into_iter().chain(path)),
+            ),
+            IndexRecordOption::Basic,
+        ))));
+    }
+
+    if let Some(vat_registered) = options.vat_registered {
+        let field = schema.get_field("vat_registered").ok()?;
+        clauses.push(filter_clause(Box::new(TermQuery::new(
+            Term::from_field_bool(field, vat_registered),
+            IndexRecordOption::Basic,
+        ))));
+    }
+
+    if options.registered_after.is_some() || options.registered_before.is_some() {
+        schema.get_field("registered_at").ok()?;
+        let bound = |date: Option<NaiveDate>| match date {
+            Some(date) => Bound::Included(indexer::date_time(date)),
+            None => Bound::Unbounded,
+        };
+        clauses.push(filter_clause(Box::new(RangeQuery::new_date_bounds(
+            "registered_at".to_string(),
+            bound(options.registered_after),
+            bound(options.registered_before),
+        ))));
+    }
+
+    let bounding_boxes = options
+        .near
+        .zip(options.radius_km)
+        .map(|(center, radius_km)| BoundingBox::around(center, radius_km))
+        .into_iter()
+        .chain(options.bounding_box);
+    for bounding_box in bounding_boxes {
+        for (field_name, min, max) in [
+            ("lat", bounding_box.min_lat, bounding_box.max_lat),
+            ("lon", bounding_box.min_lon, bounding_box.max_lon),
+        ] {
+            schema.get_field(field_name).ok()?;
+            clauses.push(filter_clause(Box::new(RangeQuery::new_f64_bounds(
+                field_name.to_string(),
+                Bound::Included(min),
+                Bound::Included(max),
+            ))));
+        }
+    }
+    if options.near.is_some() {
+        schema.get_field("lat").ok()?;
+    }
+
+    Some(Box::new(BooleanQuery::new(clauses)))
+}
+
+/// Collects the top hits ordered by `options.sort`, together with their distance from
+/// `options.near`. The bounding box filter only narrows hits down to a square around the
+/// point, so hits outside of the radius are dropped here.
+fn top_docs_near(
+    searcher: &Searcher,
+    query: &dyn Query,
+    limit: usize,
+    center: Point,
+    options: &SearchOptions,
+) -> tantivy::Result<Vec<(Score, DocAddress, Option<f64>)>> {
+    let (radius_km, sort) = (options.radius_km, options.sort);
+    let collector =
+        TopDocs::with_limit(limit).tweak_score(move |segment_reader: &SegmentReader| {
+            let lat = segment_reader.fast_fields().f64("lat").ok();
+            let lon = segment_reader.fast_fields().f64("lon").ok();
+
+            move |doc: DocId, score: Score| {
+                let distance_km = lat
+                    .as_ref()
+                    .zip(lon.as_ref())
+                    .and_then(|(lat, lon)| lat.first(doc).zip(lon.first(doc)))
+                    .map(|(lat, lon)| geo::distance_km(center, Point { lat, lon }));
+                let within = distance_km.is_some_and(|distance_km| {
+                    radius_km.is_none_or(|radius_km| distance_km <= radius_km)
+                });
+                let rank = match (sort, within) {
+                    (_, false) => f64::NEG_INFINITY,
+                    (SortOrder::Distance, true) => -distance_km.unwrap_or(f64::INFINITY),
+                    (SortOrder::Relevance, true) => 0.0,
+                };
+                (rank, score, distance_km)
+            }
+        });
+
+    Ok(searcher
+        .search(query, &collector)?
+        .into_iter()
+        .filter(|((rank, _, _), _)| *rank > f64::NEG_INFINITY)
+        .map(|((_, score, distance_km), doc_address)| (score, doc_address, distance_km))
+        .collect())
+}
+
+fn count_facets(
+    searcher: &Searcher,
+    query: &dyn Query,
+    dimensions: &[String],
+    facet_counts: &mut FacetCounts,
+) -> tantivy::Result<()> {
+    let mut facet_collector = FacetCollector::for_field(FACETS_FIELD);
+    let dimensions: Vec<&String> = dimensions
+        .iter()
+        .filter(|dimension| FACET_DIMENSIONS.contains(&dimension.as_str()))
+        .collect();
+    if dimensions.is_empty() {
+        return Ok(());
+    }
+    for dimension in &dimensions {
+        facet_collector.add_facet(Facet::from_path([dimension.as_str()]));
+    }
+
+    let counts = searcher.search(query, &facet_collector)?;
+    for dimension in dimensions {
+        let dimension_counts = facet_counts.entry(dimension.clone()).or_default();
+        for (facet, count) in counts.get(Facet::from_path([dimension.as_str()])) {
+            if let Some(value) = facet.to_path().last() {
+                *dimension_counts.entry(value.to_string()).or_default() += count;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn snippet_generators(
+    searcher: &Searcher,
+    schema: &Schema,
+    query: &dyn Query,
+) -> tantivy::Result<Vec<(&'static str, SnippetGenerator)>> {
+    HIGHLIGHT_FIELDS
+        .into_iter()
+        .filter_map(|field_name| Some((field_name, schema.get_field(field_name).ok()?)))
+        .map(|(field_name, field)| {
+            Ok((
+                field_name,
+                SnippetGenerator::create(searcher, query, field)?,
+            ))
+        })
+        .collect()
+}
+
+fn snippets(
+    snippet_generators: &[(&'static str, SnippetGenerator)],
+    document: &TantivyDocument,
+    highlight: &Highlight,
+) -> HashMap<String, String> {
+    snippet_generators
+        .iter()
+        .filter_map(|(field_name, snippet_generator)| {
+            let mut snippet = snippet_generator.snippet_from_doc(document);
+            if snippet.highlighted().is_empty() {
+                return None;
+            }
+            snippet.set_snippet_prefix_postfix(&highlight.pre_tag, &highlight.post_tag);
+            Some((field_name.to_string(), snippet.to_html()))
+        })
+        .collect()
+}
+
+fn analyze_query(
+    index: &Index,
+    fields: &[Field],
+    query_text: &str,
+) -> tantivy::Result<HashMap<String, Vec<String>>> {
+    let schema = index.schema();
+    fields
+        .iter()
+        .map(|field| {
+            let mut analyzer = index.tokenizer_for_field(*field)?;
+            let mut tokens = Vec::new();
+            analyzer
+                .token_stream(query_text)
+                .process(&mut |token| tokens.push(token.text.clone()));
+            Ok((schema.get_field_name(*field).to_string(), tokens))
+        })
+        .collect()
+}
+
+/// Fields a query is matched against when it does not name one.
+fn default_fields(schema: &Schema, mode: MatchMode) -> Vec<Field> {
+    schema
+        .fields()
+        .filter(|(_, field_entry)| {
+            field_entry.is_indexed()
+                && matches!(field_entry.field_type(), FieldType::Str(_))
+                && !field_entry.name().ends_with(PREFIX_SUFFIX)
+                && (mode == MatchMode::Phonetic || !field_entry.name().ends_with(PHONETIC_SUFFIX))
+        })
+        .map(|(field, _)| field)
+        .collect()
+}
+
+fn query_parser(index: &Index, fields: Vec<Field>) -> QueryParser {
+    let schema = index.schema();
+    let mut query_parser = QueryParser::for_index(index, fields.clone());
+    for field in fields {
+        if schema.get_field_name(field).ends_with(PHONETIC_SUFFIX) {
+            query_parser.set_field_boost(field, PHONETIC_BOOST);
+        }
+    }
+    query_parser
+}
+
+/// The first of [`NAME_FIELDS`] the schema has.
+fn name_field(schema: &Schema) -> Option<(&'static str, Field)> {
+    NAME_FIELDS
+        .into_iter()
+        .find_map(|field_name| Some((field_name, schema.get_field(field_name).ok()?)))
+}
+
+/// Matches any word of the query against the words of the name, with more typos allowed
+/// in longer words, and against how they sound when the name has a phonetic field.
+fn name_query(index: &Index, query_text: &str) -> Option<Box<dyn Query>> {
+    let schema = index.schema();
+    let (field_name, field) = name_field(&schema)?;
+
+    let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+    let mut analyzer = index.tokenizer_for_field(field).ok()?;
+    analyzer.token_stream(query_text).process(&mut |token| {
+        let distance = match token.text.chars().count() {
+            0..4 => 0,
+            4..8 => 1,
+            _ => 2,
+        };
+        clauses.push((
+            Occur::Should,
+            Box::new(FuzzyTermQuery::new(
+                Term::from_field_text(field, &token.text),
+                distance,
+                true,
+            )),
+        ));
+    });
+    if let Ok(phonetic_field) = schema.get_field(&format!("{}{}", field_name, PHONETIC_SUFFIX))
+        && let Ok(mut analyzer) = index.tokenizer_for_field(phonetic_field)
+    {
+        analyzer.token_stream(query_text).process(&mut |token| {
+            clauses.push((
+                Occur::Should,
+                Box::new(TermQuery::new(
+                    Term::from_field_text(phonetic_field, &token.text),
+                    IndexRecordOption::WithFreqs,
+                )),
+            ));
+        });
+    }
+
+    (!clauses.is_empty()).then(|| Box::new(BooleanQuery::new(clauses)) as Box<dyn Query>)
+}
+
+/// Looks up any of the values the identifier may be stored as. Returns `None` when the
+/// source does not have the field.
+fn identifier_query(schema: &Schema, identifier: &Identifier) -> Option<Box<dyn Query>> {
+    let (field_name, values) = identifier.terms();
+    let field = schema.get_field(field_name).ok()?;
+    let clauses: Vec<(Occur, Box<dyn Query>)> = values
+        .into_iter()
+        .map(|value| {
+            (
+                Occur::Should,
+                Box::new(TermQuery::new(
+                    Term::from_field_text(field, &value),
+                    IndexRecordOption::Basic,
+                )) as Box<dyn Query>,
+            )
+        })
+        .collect();
+    Some(Box::new(BooleanQuery::new(clauses)))
+}
+
+/// Parses `query_text` as `options.mode` asks for and narrows it down with the filters.
+/// Returns `None` when nothing in this source can match.
+fn build_query(
+    source_name: SourceName,
+    index: &Index,
+    query_text: &str,
+    options: &SearchOptions,
+) -> Result<Option<Box<dyn Query>>, QueryParserError> {
+    let schema = index.schema();
+    let query = match options.mode {
+        MatchMode::Name => name_query(index, query_text),
+        MatchMode::Lexical | MatchMode::Phonetic => Some(
+            query_parser(index, default_fields(&schema, options.mode)).parse_query(query_text)?,
+        ),
+    };
+    Ok(query.and_then(|query| apply_filters(source_name, &schema, query, options)))
+}
+
+/// Counts the hits of `query_text` in one source, with the filters of `options` applied.
+pub fn count_hits(
+    source_name: SourceName,
+    index: &Index,
+    reader: &IndexReader,
+    query_text: &str,
+    options: &SearchOptions,
+) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+    let Some(query) = build_query(source_name, index, query_text, options)? else {
+        return Ok(0);
+    };
+    Ok(reader.searcher().search(&query, &Count)? as u64)
+}
+
+/// An index and a reader of every selected source that is open.
+pub type SourceSnapshot = Vec<(SourceName, Index, IndexReader)>;
+
+/// Indexes and readers are cheap to clone, so the maps are not held locked while
+/// searching and concurrent searches run in parallel.
+pub async fn snapshot_sources(
+    indexes: &Arc<Mutex<IndexMap>>,
+    readers: &Arc<Mutex<ReaderMap>>,
+    selected_sources: &HashSet<SourceName>,
+) -> SourceSnapshot {
+    let (indexes_map, readers_map) = (indexes.lock().await, readers.lock().await);
+    selected_sources
+        .iter()
+        .filter_map(|source_name| {
+            Some((
+                *source_name,
+                indexes_map.get(source_name)?.clone(),
+                readers_map.get(source_name)?.clone(),
+            ))
+        })
+        .collect()
+}
 
 pub async fn search_indexes(
     indexes: &Arc<Mutex<IndexMap>>,
     readers: &Arc<Mutex<ReaderMap>>,
     selected_sources: HashSet<SourceName>,
     query: String,
-    maybe_limit: Option<usize>,
-) -> Result<SearchResults, Box<dyn std::error::Error + Send + Sync>> {
-    let limit = maybe_limit.unwrap_or(10);
-    let (indexes_map, readers_map) = (indexes.lock().await, readers.lock().await);
+    options: SearchOptions,
+) -> Result<SearchResponse, Box<dyn std::error::Error + Send + Sync>> {
+    let sources = snapshot_sources(indexes, readers, &selected_sources).await;
+    tokio::task::spawn_blocking(move || search_sources(&sources, &query, options)).await?
+}
 
-    let mut search_results: SearchResults = HashMap::new();
-    selected_sources.iter().for_each(|source_name| {
-        if let (Some(index), Some(reader)) =
-            (indexes_map.get(source_name), readers_map.get(source_name))
-        {
-            info!("Searching in {} for {:#?}", source_name, query);
+/// Searches the sources of a snapshot. This is synchronous CPU bound work, so callers
+/// that run many searches at once do it on blocking threads.
+pub fn search_sources(
+    sources: &[(SourceName, Index, IndexReader)],
+    query: &str,
+    options: SearchOptions,
+) -> Result<SearchResponse, Box<dyn std::error::Error + Send + Sync>> {
+    let limit = options.limit.unwrap_or(10);
+    if limit == 0 {
+        return Err("Limit must be at least 1".into());
+    }
 
+    // A tax number or company id pasted into the search box is looked up exactly, and only
+    // searched for as text when no source has it.
+    let identifier = validation::detect_identifier(query).filter(|identifier| {
+        sources.iter().any(|(source_name, index, reader)| {
             let schema = index.schema();
-            let all_fields: Vec<Field> = schema.fields().map(|(field, _)| field).collect();
-            let query_parser = QueryParser::for_index(index, all_fields);
+            identifier_query(&schema, identifier)
+                .and_then(|query| apply_filters(*source_name, &schema, query, &options))
+                .is_some_and(|query| reader.searcher().search(&query, &Count).unwrap_or(0) > 0)
+        })
+    });
+    let options = match identifier {
+        Some(_) => SearchOptions {
+            mode: MatchMode::Lexical,
+            rerank: false,
+            ..options
+        },
+        None => options,
+    };
 
-            let query = query_parser.parse_query(&query).unwrap();
-            let searcher = reader.searcher();
-            let top_docs = searcher
-                .search(&query, &TopDocs::with_limit(limit))
-                .unwrap();
-
-            let mut documents: Vec<IndexResult> = Vec::new();
-            for (score, doc_address) in top_docs {
-                let document: TantivyDocument = searcher.doc(doc_address).unwrap();
-                documents.push((
-                    score,
-                    document.to_named_doc(&schema),
-                    document.to_json(&schema).clone(),
-                ));
+    let mut search_results: SearchResults = HashMap::new();
+    let mut facet_counts: FacetCounts = HashMap::new();
+    let mut debug: HashMap<SourceName, QueryDebug> = HashMap::new();
+    for (source_name, index, reader) in sources {
+        info!("Searching in {} for {:#?}", source_name, query);
+
+        let schema = index.schema();
+        let all_fields = default_fields(&schema, options.mode);
+        let analyzed = if options.explain {
+            analyze_query(index, &all_fields, query)?
+        } else {
+            HashMap::new()
+        };
+        let parsed_query = match &identifier {
+            Some(identifier) => identifier_query(&schema, identifier)
+                .and_then(|query| apply_filters(*source_name, &schema, query, &options)),
+            None => build_query(*source_name, index, query, &options)?,
+        };
+        let Some(parsed_query) = parsed_query else {
+            search_results.insert(*source_name, vec![]);
+            continue;
+        };
+        if options.explain {
+            debug.insert(
+                *source_name,
+                QueryDebug {
+                    parsed_query: format!("{:?}", parsed_query),
+                    analyzed,
+                },
+            );
+        }
+        let searcher = reader.searcher();
+        let count = searcher.search(&parsed_query, &Count)?;
+        let candidates = match options.mode {
+            MatchMode::Name => limit * NAME_CANDIDATES_PER_RESULT,
+            MatchMode::Lexical | MatchMode::Phonetic => limit,
+        };
+        let candidates = if options.rerank {
+            candidates.max(RERANK_CANDIDATES)
+        } else {
+            candidates
+        };
+        let top_docs = match options.near {
+            Some(center) => top_docs_near(
+                &searcher,
+                parsed_query.as_ref(),
+                candidates,
+                center,
+                &options,
+            )?,
+            None => searcher
+                .search(&parsed_query, &TopDocs::with_limit(candidates))?
+                .into_iter()
+                .map(|(score, doc_address)| (score, doc_address, None))
+                .collect(),
+        };
+
+        if options.facets.iter().any(|dimension| dimension == "source") {
+            facet_counts
+                .entry("source".to_string())
+                .or_default()
+                .insert(source_name.to_string(), count as u64);
+        }
+        count_facets(
+            &searcher,
+            parsed_query.as_ref(),
+            &options.facets,
+            &mut facet_counts,
+        )?;
+
+        let snippet_generators = match &options.highlight {
+            Some(_) => snippet_generators(&searcher, &schema, parsed_query.as_ref())?,
+            None => vec![],
+        };
+
+        let mut documents: Vec<IndexResult> = Vec::new();
+        for (score, doc_address, distance_km) in top_docs {
+            let document: TantivyDocument = searcher.doc(doc_address)?;
+            let snippets = options
+                .highlight
+                .as_ref()
+                .map(|highlight| snippets(&snippet_generators, &document, highlight))
+                .unwrap_or_default();
+            let explanation = if options.explain {
+                Some(serde_json::to_value(
+                    parsed_query.explain(&searcher, doc_address)?,
+                )?)
+            } else {
+                None
+            };
+            let name = name_field(&schema)
+                .and_then(|(field_name, _)| documents::stored_text(&document, &schema, field_name))
+                .map(String::from);
+            let similarity = match options.mode {
+                MatchMode::Name => Some(similarity::token_set_similarity(
+                    query,
+                    name.as_deref().unwrap_or_default(),
+                )),
+                MatchMode::Lexical | MatchMode::Phonetic => None,
+            };
+            let confidence = options
+                .rerank
+                .then(|| similarity::match_confidence(query, name.as_deref().unwrap_or_default()));
+            if similarity.is_some_and(|similarity| similarity < MIN_NAME_SIMILARITY) {
+                continue;
             }
+            documents.push(IndexResult {
+                score,
+                document: document.to_named_doc(&schema),
+                json: document.to_json(&schema),
+                distance_km,
+                snippets,
+                explanation,
+                similarity,
+                confidence,
+            });
+        }
+        if options.sort == SortOrder::Relevance {
+            // The confidence of reranking takes precedence over the similarity.
+            documents.sort_by(|a, b| {
+                let a_rank = a.confidence.or(a.similarity).unwrap_or_default();
+                let b_rank = b.confidence.or(b.similarity).unwrap_or_default();
+                b_rank.total_cmp(&a_rank).then(b.score.total_cmp(&a.score))
+            });
+        }
+        documents.truncate(limit);
+
+        search_results.insert(*source_name, documents.into_iter().collect());
+    }
 
-            search_results.insert(*source_name, documents.into_iter().collect());
+    Ok(SearchResponse {
+        results: search_results,
+        facets: facet_counts,
+        debug,
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct Suggestion {
+    pub source_name: SourceName,
+    pub name: String,
+    pub company_id: Option<String>,
+    pub vat_id: Option<String>,
+    pub score: Score,
+}
+
+/// Every word typed so far has to start one of the words of the name. Unlike
+/// [`search_indexes`] this only looks at the `*_prefix` fields and skips facets, filters
+/// and snippets, so it stays fast enough to run on every key stroke.
+fn autocomplete_query(index: &Index, prefix_field: Field, text: &str) -> Option<BooleanQuery> {
+    let mut analyzer = index
+        .tokenizers()
+        .get(EDGE_NGRAM_QUERY_TOKENIZER)
+        .expect("Autocomplete tokenizers are registered on every index");
+    let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+    analyzer.token_stream(text).process(&mut |token| {
+        let prefix: String = token.text.chars().take(MAX_GRAM).collect();
+        clauses.push((
+            Occur::Must,
+            Box::new(TermQuery::new(
+                Term::from_field_text(prefix_field, &prefix),
+                IndexRecordOption::WithFreqs,
+            )),
+        ));
+    });
+    (!clauses.is_empty()).then(|| BooleanQuery::new(clauses))
+}
+
+/// Most suggestions returned however many are asked for, as each one costs several hits.
+pub const MAX_AUTOCOMPLETE_SUGGESTIONS: usize = 20;
+
+/// Suggests up to `limit` distinct names that start with what was typed in `text`, best
+/// matches first. The limit is kept between 1 and [`MAX_AUTOCOMPLETE_SUGGESTIONS`].
+pub async fn autocomplete(
+    indexes: &Arc<Mutex<IndexMap>>,
+    readers: &Arc<Mutex<ReaderMap>>,
+    selected_sources: HashSet<SourceName>,
+    text: &str,
+    limit: usize,
+) -> Result<Vec<Suggestion>, Box<dyn std::error::Error + Send + Sync>> {
+    let sources = snapshot_sources(indexes, readers, &selected_sources).await;
+    let text = text.to_string();
+    let limit = limit.clamp(1, MAX_AUTOCOMPLETE_SUGGESTIONS);
+    tokio::task::spawn_blocking(move || complete_sources(&sources, &text, limit)).await?
+}
+
+fn complete_sources(
+    sources: &[(SourceName, Index, IndexReader)],
+    text: &str,
+    limit: usize,
+) -> Result<Vec<Suggestion>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut suggestions: Vec<Suggestion> = Vec::new();
+    for (source_name, index, reader) in sources {
+        let source_name = *source_name;
+        let schema = index.schema();
+        let Some((name_field, prefix_field)) = AUTOCOMPLETE_FIELDS.iter().find_map(|field_name| {
+            let prefix_field = schema
+                .get_field(&format!("{}{}", field_name, PREFIX_SUFFIX))
+                .ok()?;
+            Some((*field_name, prefix_field))
+        }) else {
+            continue;
+        };
+        let Some(query) = autocomplete_query(index, prefix_field, text) else {
+            continue;
+        };
+
+        let searcher = reader.searcher();
+        // Fetch more than needed, as the same name is often registered more than once.
+        for (score, doc_address) in searcher.search(&query, &TopDocs::with_limit(limit * 4))? {
+            let document: TantivyDocument = searcher.doc(doc_address)?;
+            let Some(name) = documents::stored_text(&document, &schema, "full_name")
+                .or_else(|| documents::stored_text(&document, &schema, name_field))
+                .map(String::from)
+            else {
+                continue;
+            };
+            suggestions.push(Suggestion {
+                source_name,
+                name,
+                company_id: documents::stored_text(&document, &schema, "company_id")
+                    .map(String::from),
+                vat_id: documents::stored_text(&document, &schema, "vat_id").map(String::from),
+                score,
+            });
         }
+    }
+
+    // Shorter names first among equal scores, as they are closer to what was typed.
+    suggestions.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then(a.name.len().cmp(&b.name.len()))
     });
+    let mut seen_names = HashSet::new();
+    suggestions.retain(|suggestion| seen_names.insert(suggestion.name.to_lowercase()));
+    suggestions.truncate(limit);
+
+    Ok(suggestions)
+}
+
+/// Fields an address query is matched against. Streets are stored with their abbreviations
+/// expanded, so "ulica" finds an address written with "ul." too.
+const ADDRESS_FIELDS: [&str; 2] = ["address", "street"];
+/// Hits per source looked at for the addresses that match.
+const ADDRESS_CANDIDATES: usize = 100;
+/// Entities listed per address. The count covers all of them.
+const ENTITIES_PER_ADDRESS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AddressEntity {
+    pub source_name: String,
+    pub name: String,
+    pub company_id: Option<String>,
+    pub vat_id: Option<String>,
+}
+
+/// Everything registered at one address, across sources.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AddressGroup {
+    pub address_key: String,
+    /// The address as the best matching entry writes it.
+    pub address: String,
+    /// Entries at the address in all sources, so an entity listed in more than one source
+    /// counts more than once.
+    pub count: u64,
+    pub entities: Vec<AddressEntity>,
+}
+
+/// Finds up to `limit` addresses matching every word of `text`, best matches first, with
+/// every entity registered at each of them, grouped by [`crate::address::Address::key`].
+pub async fn search_addresses(
+    indexes: &Arc<Mutex<IndexMap>>,
+    readers: &Arc<Mutex<ReaderMap>>,
+    selected_sources: HashSet<SourceName>,
+    text: &str,
+    limit: usize,
+) -> Result<Vec<AddressGroup>, Box<dyn std::error::Error + Send + Sync>> {
+    let sources = snapshot_sources(indexes, readers, &selected_sources).await;
+    let query_text = plain_query(&address::normalize_street(text));
+    if query_text.trim().is_empty() {
+        return Ok(vec![]);
+    }
+
+    // The best score of every address key and how the address is written there.
+    let mut matches: HashMap<String, (Score, String)> = HashMap::new();
+    for (_, index, reader) in &sources {
+        let schema = index.schema();
+        let fields: Vec<Field> = ADDRESS_FIELDS
+            .iter()
+            .filter_map(|field_name| schema.get_field(field_name).ok())
+            .collect();
+        if fields.is_empty() {
+            continue;
+        }
+        let mut query_parser = QueryParser::for_index(index, fields);
+        query_parser.set_conjunction_by_default();
+        let query = query_parser.parse_query(&query_text)?;
+
+        let searcher = reader.searcher();
+        for (score, doc_address) in
+            searcher.search(&query, &TopDocs::with_limit(ADDRESS_CANDIDATES))?
+        {
+            let document: TantivyDocument = searcher.doc(doc_address)?;
+            let (Some(address_key), Some(address)) = (
+                documents::stored_text(&document, &schema, "address_key").map(String::from),
+                documents::stored_text(&document, &schema, "address").map(String::from),
+            ) else {
+                continue;
+            };
+            let best = matches
+                .entry(address_key)
+                .or_insert_with(|| (score, address.clone()));
+            if score > best.0 {
+                *best = (score, address);
+            }
+        }
+    }
+    let mut matches: Vec<(String, (Score, String))> = matches.into_iter().collect();
+    matches.sort_by(|(_, (a, _)), (_, (b, _))| b.total_cmp(a));
+    matches.truncate(limit);
+
+    let mut groups = Vec::new();
+    for (address_key, (_, address)) in matches {
+        let mut group = AddressGroup {
+            address_key,
+            address,
+            count: 0,
+            entities: vec![],
+        };
+        for (source_name, index, reader) in &sources {
+            let schema = index.schema();
+            let Ok(address_key_field) = schema.get_field("address_key") else {
+                continue;
+            };
+            let query = TermQuery::new(
+                Term::from_field_text(address_key_field, &group.address_key),
+                IndexRecordOption::Basic,
+            );
+            let searcher = reader.searcher();
+            let (count, top_docs) =
+                searcher.search(&query, &(Count, TopDocs::with_limit(ENTITIES_PER_ADDRESS)))?;
+            group.count += count as u64;
+            for (_, doc_address) in top_docs {
+                let document: TantivyDocument = searcher.doc(doc_address)?;
+                let Some(name) = documents::stored_text(&document, &schema, "full_name")
+                    .or_else(|| {
+                        name_field(&schema).and_then(|(field_name, _)| {
+                            documents::stored_text(&document, &schema, field_name)
+                        })
+                    })
+                    .map(String::from)
+                else {
+                    continue;
+                };
+                group.entities.push(AddressEntity {
+                    source_name: source_name.to_string(),
+                    name,
+                    company_id: documents::stored_text(&document, &schema, "company_id")
+                        .map(String::from),
+                    vat_id: documents::stored_text(&document, &schema, "vat_id").map(String::from),
+                });
+            }
+        }
+        group.entities.sort_by(|a, b| {
+            a.source_name
+                .cmp(&b.source_name)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        groups.push(group);
+    }
 
-    Ok(search_results)
+    Ok(groups)
 }
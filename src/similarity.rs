@@ -0,0 +1,64 @@
+use crate::legal_form::{self, fold};
+
+/// Lowest similarity two words may have to be paired up as the same word.
+const MIN_WORD_SIMILARITY: f64 = 0.75;
+/// How much a word of the name that was not asked for, like a middle name, counts against
+/// the similarity compared to a word that was asked for but not found.
+const EXTRA_WORD_WEIGHT: f64 = 0.5;
+
+/// Lowercase words of `text` without carons, e.g. "NOVAK  Janez" into `["novak", "janez"]`.
+pub fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.chars().map(fold).collect())
+        .collect()
+}
+
+/// Similarity of `query` and `name` as sets of words, from 0 to 1, regardless of the order
+/// the words are in. Every word of the query is paired with the most similar word of the
+/// name that is left, allowing for typos.
+pub fn token_set_similarity(query: &str, name: &str) -> f64 {
+    let query_words = words(query);
+    let mut name_words = words(name);
+    if query_words.is_empty() || name_words.is_empty() {
+        return 0.0;
+    }
+
+    let mut matched = 0.0;
+    for query_word in &query_words {
+        let best = name_words
+            .iter()
+            .enumerate()
+            .map(|(position, name_word)| {
+                (
+                    position,
+                    strsim::normalized_levenshtein(query_word, name_word),
+                )
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+        if let Some((position, similarity)) = best
+            && similarity >= MIN_WORD_SIMILARITY
+        {
+            matched += similarity;
+            name_words.swap_remove(position);
+        }
+    }
+
+    matched / (query_words.len() as f64 + EXTRA_WORD_WEIGHT * name_words.len() as f64)
+}
+
+/// Weight of the Jaro-Winkler similarity in [`match_confidence`], the rest goes to the
+/// token set similarity.
+const JARO_WINKLER_WEIGHT: f64 = 0.4;
+
+/// Confidence from 0 to 1 that `name` is what `query` is looking for. Blends the
+/// Jaro-Winkler similarity of the whole names, which favours a shared beginning and catches
+/// typos within words, with the [`token_set_similarity`], which ignores word order. Legal
+/// forms are left out of both sides, so "Opalab d.o.o." is as close to "opalab" as "Opalab".
+pub fn match_confidence(query: &str, name: &str) -> f64 {
+    let (query, _) = legal_form::split_legal_form(query);
+    let (name, _) = legal_form::split_legal_form(name);
+    let jaro_winkler = strsim::jaro_winkler(&words(&query).join(" "), &words(&name).join(" "));
+    JARO_WINKLER_WEIGHT * jaro_winkler
+        + (1.0 - JARO_WINKLER_WEIGHT) * token_set_similarity(&query, &name)
+}
@@ -0,0 +1,114 @@
+use csv::ReaderBuilder;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+/// The SKD 2008 standard classification of activities, the Slovenian edition of NACE Rev. 2,
+/// from sections down to classes. Subclasses are only listed where SKD splits a class, the
+/// others share the label of their class.
+const SKD_CSV: &str = include_str!("../data/skd.csv");
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SkdEntry {
+    /// Section letter, e.g. "F", or a code written as in [`normalize_code`], e.g. "41" or
+    /// "41.20".
+    pub code: String,
+    pub section: String,
+    pub label: String,
+}
+
+static SKD: LazyLock<HashMap<String, SkdEntry>> = LazyLock::new(|| {
+    ReaderBuilder::new()
+        .has_headers(true)
+        .from_reader(SKD_CSV.as_bytes())
+        .deserialize::<SkdEntry>()
+        .map(|record| record.expect("Bundled SKD table is malformed"))
+        .map(|entry| (entry.code.clone(), entry))
+        .collect()
+});
+
+/// Writes an activity code the way the registries do, e.g. "41200" or "41.2" into
+/// "41.200" and "41.2". Returns `None` for anything that is not a code of a known division.
+pub fn normalize_code(raw: &str) -> Option<String> {
+    let digits: String = raw.trim().chars().filter(|c| *c != '.').collect();
+    if !(2..=5).contains(&digits.len()) || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    SKD.get(&digits[..2])?;
+    Some(match digits.len() {
+        2 => digits,
+        _ => format!("{}.{}", &digits[..2], &digits[2..]),
+    })
+}
+
+/// Finds the activity code in a registry column that may hold more than the code, like the
+/// fixed width slice of the tax registry, e.g. "41.200" in "41.200  2". The code is returned
+/// as the registry wrote it.
+pub fn find_code(text: &str) -> Option<&str> {
+    text.split_whitespace()
+        .find(|token| normalize_code(token).is_some())
+}
+
+/// The most specific listed level of an activity code, e.g. class "41.20" with section "F"
+/// and label "Gradnja stanovanjskih in nestanovanjskih stavb" for "41.200".
+pub fn lookup(code: &str) -> Option<&'static SkdEntry> {
+    path(code)?.iter().rev().find_map(|level| SKD.get(level))
+}
+
+/// Path of an activity code from its section down to the code itself, e.g.
+/// `["F", "41", "41.2", "41.20", "41.200"]` for "41.200". A section letter on its own is a
+/// path too.
+pub fn path(code: &str) -> Option<Vec<String>> {
+    let code = code.trim();
+    if let Some(entry) = SKD.get(&code.to_uppercase())
+        && entry.code == entry.section
+    {
+        return Some(vec![entry.section.clone()]);
+    }
+
+    let code = normalize_code(code)?;
+    let division = &code[..2];
+    let mut path = vec![SKD.get(division)?.section.clone(), division.to_string()];
+    for end in 4..=code.len() {
+        path.push(code[..end].to_string());
+    }
+    Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_run_from_the_section_down_to_the_code() {
+        assert_eq!(
+            path("41.200").unwrap(),
+            ["F", "41", "41.2", "41.20", "41.200"]
+        );
+        assert_eq!(path("41200").unwrap(), path("41.200").unwrap());
+        assert_eq!(path("47.11").unwrap(), ["G", "47", "47.1", "47.11"]);
+        assert_eq!(path("f").unwrap(), ["F"]);
+    }
+
+    #[test]
+    fn unknown_codes_have_no_path() {
+        assert_eq!(path("00.100"), None);
+        assert_eq!(path("4"), None);
+        assert_eq!(path("abc"), None);
+    }
+
+    #[test]
+    fn looks_up_the_deepest_listed_level() {
+        assert_eq!(lookup("47.110").unwrap().code, "47.11");
+        assert_eq!(lookup("41.200").unwrap().section, "F");
+    }
+
+    #[test]
+    fn finds_the_code_among_the_rest_of_a_column() {
+        assert_eq!(find_code("52.421            "), Some("52.421"));
+        assert_eq!(find_code("  41200 2 X"), Some("41200"));
+        assert_eq!(find_code("1 41.200"), Some("41.200"));
+        assert_eq!(find_code("   "), None);
+        assert_eq!(find_code("ABC 00.100"), None);
+    }
+}
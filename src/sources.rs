@@ -38,7 +38,7 @@ async fn get_first_zip_link_safe(
     let download_url = if download_url.starts_with("http") {
         download_url
     } else {
-        let base_url = reqwest::Url::parse(&source_config.source_url)?;
+        let base_url = reqwest::Url::parse(source_config.source_url)?;
         base_url.join(&download_url)?.to_string()
     };
 
@@ -0,0 +1,240 @@
+use crate::indexer::IndexMap;
+use crate::search::{self, ReaderMap, SearchOptions};
+use crate::tpconfig::SourceName;
+use levenshtein_automata::{DFA, Distance, LevenshteinAutomatonBuilder, SINK_STATE};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, LazyLock};
+use tantivy::tokenizer::{LowerCaser, SimpleTokenizer, TextAnalyzer};
+use tantivy::{Index, IndexReader, Searcher};
+use tantivy_fst::Automaton;
+use tokio::sync::Mutex;
+
+/// Fields whose terms corrections are picked from.
+const VOCABULARY_FIELDS: [&str; 2] = ["company_name", "name"];
+/// Searches returning fewer hits than this get "did you mean" suggestions.
+pub const SPARSE_HITS: usize = 3;
+/// Words shorter than this are left alone, as too many terms are a typo away from them.
+const MIN_WORD_LENGTH: usize = 3;
+/// Corrections tried for each word that no index knows.
+const CORRECTIONS_PER_WORD: usize = 3;
+
+/// Building the tables of a Levenshtein automaton is costly, so it is done once per distance.
+static AUTOMATON_BUILDERS: LazyLock<[LevenshteinAutomatonBuilder; 2]> = LazyLock::new(|| {
+    [
+        LevenshteinAutomatonBuilder::new(1, true),
+        LevenshteinAutomatonBuilder::new(2, true),
+    ]
+});
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuerySuggestion {
+    pub query: String,
+    /// Hits of the suggested query over the searched sources.
+    pub hits: u64,
+}
+
+/// Lets the term dictionary, an FST, be walked with a Levenshtein automaton so only the
+/// terms within the allowed distance are visited.
+struct LevenshteinAutomaton<'a>(&'a DFA);
+
+impl Automaton for LevenshteinAutomaton<'_> {
+    type State = u32;
+
+    fn start(&self) -> u32 {
+        self.0.initial_state()
+    }
+
+    fn is_match(&self, state: &u32) -> bool {
+        matches!(self.0.distance(*state), Distance::Exact(_))
+    }
+
+    fn can_match(&self, state: &u32) -> bool {
+        *state != SINK_STATE
+    }
+
+    fn accept(&self, state: &u32, byte: u8) -> u32 {
+        self.0.transition(*state, byte)
+    }
+}
+
+/// Whether any of the name fields of `searcher` has `word` as a term.
+fn is_known(searcher: &Searcher, word: &str) -> tantivy::Result<bool> {
+    let schema = searcher.schema();
+    for field in VOCABULARY_FIELDS
+        .into_iter()
+        .filter_map(|field_name| schema.get_field(field_name).ok())
+    {
+        for segment_reader in searcher.segment_readers() {
+            if segment_reader
+                .inverted_index(field)?
+                .terms()
+                .term_ord(word)?
+                .is_some()
+            {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Adds the terms of the name fields of `searcher` within the edit distance allowed for
+/// `word` to `candidates`, with that distance and how many documents they occur in.
+fn add_corrections(
+    searcher: &Searcher,
+    word: &str,
+    candidates: &mut HashMap<String, (usize, u64)>,
+) -> tantivy::Result<()> {
+    let max_distance = if word.chars().count() <= 4 { 1 } else { 2 };
+    let dfa = AUTOMATON_BUILDERS[max_distance - 1].build_dfa(word);
+    let schema = searcher.schema();
+    for field in VOCABULARY_FIELDS
+        .into_iter()
+        .filter_map(|field_name| schema.get_field(field_name).ok())
+    {
+        for segment_reader in searcher.segment_readers() {
+            let inverted_index = segment_reader.inverted_index(field)?;
+            let mut stream = inverted_index
+                .terms()
+                .search(LevenshteinAutomaton(&dfa))
+                .into_stream()?;
+            while stream.advance() {
+                let Ok(term) = std::str::from_utf8(stream.key()) else {
+                    continue;
+                };
+                let Distance::Exact(distance) = dfa.eval(term) else {
+                    continue;
+                };
+                candidates
+                    .entry(term.to_string())
+                    .or_insert((distance as usize, 0))
+                    .1 += stream.value().doc_freq as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Suggests up to `limit` corrections of `query` that do have hits. Each word that is not a
+/// term of the selected indexes is replaced by its closest terms, preferring the ones that
+/// occur in more documents. This walks the term dictionaries, so it runs on a blocking thread.
+pub async fn suggest(
+    indexes: &Arc<Mutex<IndexMap>>,
+    readers: &Arc<Mutex<ReaderMap>>,
+    selected_sources: &HashSet<SourceName>,
+    query: &str,
+    options: &SearchOptions,
+    limit: usize,
+) -> Result<Vec<QuerySuggestion>, Box<dyn std::error::Error + Send + Sync>> {
+    let sources = search::snapshot_sources(indexes, readers, selected_sources).await;
+    let (query, options) = (query.to_string(), options.clone());
+    tokio::task::spawn_blocking(move || suggest_sources(&sources, &query, &options, limit)).await?
+}
+
+fn suggest_sources(
+    sources: &[(SourceName, Index, IndexReader)],
+    query: &str,
+    options: &SearchOptions,
+    limit: usize,
+) -> Result<Vec<QuerySuggestion>, Box<dyn std::error::Error + Send + Sync>> {
+    let searchers: Vec<Searcher> = sources
+        .iter()
+        .map(|(_, _, reader)| reader.searcher())
+        .collect();
+
+    let mut analyzer = TextAnalyzer::builder(SimpleTokenizer::default())
+        .filter(LowerCaser)
+        .build();
+    let mut words: Vec<String> = Vec::new();
+    analyzer
+        .token_stream(query)
+        .process(&mut |token| words.push(token.text.clone()));
+
+    // Closest terms for every unknown word, best first.
+    let mut corrections: Vec<(usize, Vec<(String, usize)>)> = Vec::new();
+    for (position, word) in words.iter().enumerate() {
+        if word.chars().count() < MIN_WORD_LENGTH {
+            continue;
+        }
+        let mut known = false;
+        for searcher in &searchers {
+            known |= is_known(searcher, word)?;
+        }
+        if known {
+            continue;
+        }
+        let mut candidates: HashMap<String, (usize, u64)> = HashMap::new();
+        for searcher in &searchers {
+            add_corrections(searcher, word, &mut candidates)?;
+        }
+        let mut candidates: Vec<(String, (usize, u64))> = candidates.into_iter().collect();
+        candidates.sort_by(|(a, (a_distance, a_freq)), (b, (b_distance, b_freq))| {
+            a_distance
+                .cmp(b_distance)
+                .then(b_freq.cmp(a_freq))
+                .then(a.cmp(b))
+        });
+        let candidates: Vec<(String, usize)> = candidates
+            .into_iter()
+            .take(CORRECTIONS_PER_WORD)
+            .map(|(term, (distance, _))| (term, distance))
+            .collect();
+        if !candidates.is_empty() {
+            corrections.push((position, candidates));
+        }
+    }
+    if corrections.is_empty() {
+        return Ok(vec![]);
+    }
+
+    // Try every correction of one word while the other words take their best correction.
+    let mut candidate_queries: Vec<(String, usize)> = Vec::new();
+    for (varied_position, varied_candidates) in &corrections {
+        for (varied_term, varied_distance) in varied_candidates {
+            let mut corrected = words.clone();
+            let mut distance = *varied_distance;
+            for (position, candidates) in &corrections {
+                if position == varied_position {
+                    corrected[*position] = varied_term.clone();
+                } else {
+                    corrected[*position] = candidates[0].0.clone();
+                    distance += candidates[0].1;
+                }
+            }
+            let corrected = corrected.join(" ");
+            if candidate_queries
+                .iter()
+                .all(|(query, _)| *query != corrected)
+            {
+                candidate_queries.push((corrected, distance));
+            }
+        }
+    }
+
+    let mut suggestions: Vec<(QuerySuggestion, usize)> = Vec::new();
+    for (candidate_query, distance) in candidate_queries {
+        let mut hits = 0;
+        for (source_name, index, reader) in sources {
+            hits += search::count_hits(*source_name, index, reader, &candidate_query, options)?;
+        }
+        if hits > 0 {
+            suggestions.push((
+                QuerySuggestion {
+                    query: candidate_query,
+                    hits,
+                },
+                distance,
+            ));
+        }
+    }
+    suggestions.sort_by(|(a, a_distance), (b, b_distance)| {
+        a_distance.cmp(b_distance).then(b.hits.cmp(&a.hits))
+    });
+
+    Ok(suggestions
+        .into_iter()
+        .take(limit)
+        .map(|(suggestion, _)| suggestion)
+        .collect())
+}
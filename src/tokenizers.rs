@@ -0,0 +1,283 @@
+use tantivy::Index;
+use tantivy::tokenizer::{
+    AsciiFoldingFilter, LowerCaser, RemoveLongFilter, SimpleTokenizer, TextAnalyzer, Token,
+    TokenFilter, TokenStream, Tokenizer,
+};
+
+/// Indexes every prefix of every word, for the `*_prefix` autocomplete fields.
+pub const EDGE_NGRAM_TOKENIZER: &str = "edge_ngram";
+/// Analyzes what was typed into an autocomplete box the same way, minus the prefixes.
+pub const EDGE_NGRAM_QUERY_TOKENIZER: &str = "edge_ngram_query";
+/// Reduces names to how they sound in Slovenian, for the `*_phonetic` fields.
+pub const PHONETIC_TOKENIZER: &str = "slovenian_phonetic";
+/// Longest prefix that is indexed. Longer words typed in are cut down to it when searching.
+pub const MAX_GRAM: usize = 15;
+
+/// Registers the custom analyzers the schemas refer to. Tantivy does not persist analyzers,
+/// so this has to run on every index that is opened or created.
+pub fn register(index: &Index) {
+    let tokenizers = index.tokenizers();
+    tokenizers.register(
+        EDGE_NGRAM_TOKENIZER,
+        TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(RemoveLongFilter::limit(40))
+            .filter(LowerCaser)
+            .filter(AsciiFoldingFilter)
+            .filter(EdgeNgramFilter::new(1, MAX_GRAM))
+            .build(),
+    );
+    tokenizers.register(
+        EDGE_NGRAM_QUERY_TOKENIZER,
+        TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(RemoveLongFilter::limit(40))
+            .filter(LowerCaser)
+            .filter(AsciiFoldingFilter)
+            .build(),
+    );
+    tokenizers.register(
+        PHONETIC_TOKENIZER,
+        TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(RemoveLongFilter::limit(40))
+            .filter(LowerCaser)
+            .filter(AsciiFoldingFilter)
+            .filter(PhoneticFilter)
+            .build(),
+    );
+}
+
+/// Spellings that sound the same in Slovenian or are how foreign names get written down,
+/// applied in this order to a lowercase, ASCII folded word.
+const PHONETIC_REWRITES: [(&str, &str); 12] = [
+    ("tsch", "c"),
+    ("sch", "s"),
+    ("ch", "c"),
+    ("ck", "k"),
+    ("tz", "c"),
+    ("ph", "f"),
+    ("th", "t"),
+    ("lj", "l"),
+    ("nj", "n"),
+    ("x", "ks"),
+    ("q", "k"),
+    ("w", "v"),
+];
+
+/// Encodes a lowercase, ASCII folded word by how it sounds, so "Šuštar" and "Šustar",
+/// "Kovačič" and "Kovacic" or "Jurjevec" and "Jurievec" get the same key. Folding already
+/// drops the carons, after which the remaining differences are `j` and `i`, doubled
+/// letters, foreign spellings and the devoiced consonant at the end of a word.
+pub fn phonetic_key(word: &str) -> String {
+    let mut key = word.to_string();
+    for (spelling, sound) in PHONETIC_REWRITES {
+        key = key.replace(spelling, sound);
+    }
+    let mut chars: Vec<char> = key
+        .chars()
+        .map(|c| match c {
+            'j' | 'y' => 'i',
+            c => c,
+        })
+        .collect();
+    chars.dedup();
+    let mut key: String = chars.into_iter().collect();
+    if let Some(last) = key.pop() {
+        key.push(match last {
+            'b' => 'p',
+            'd' => 't',
+            'g' => 'k',
+            'z' => 's',
+            c => c,
+        });
+    }
+    key
+}
+
+/// Token filter that replaces each token with its [`phonetic_key`].
+#[derive(Clone)]
+pub struct PhoneticFilter;
+
+impl TokenFilter for PhoneticFilter {
+    type Tokenizer<T: Tokenizer> = PhoneticFilterWrapper<T>;
+
+    fn transform<T: Tokenizer>(self, tokenizer: T) -> Self::Tokenizer<T> {
+        PhoneticFilterWrapper { tokenizer }
+    }
+}
+
+#[derive(Clone)]
+pub struct PhoneticFilterWrapper<T> {
+    tokenizer: T,
+}
+
+impl<T: Tokenizer> Tokenizer for PhoneticFilterWrapper<T> {
+    type TokenStream<'a> = PhoneticTokenStream<T::TokenStream<'a>>;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+        PhoneticTokenStream {
+            tail: self.tokenizer.token_stream(text),
+        }
+    }
+}
+
+pub struct PhoneticTokenStream<T> {
+    tail: T,
+}
+
+impl<T: TokenStream> TokenStream for PhoneticTokenStream<T> {
+    fn advance(&mut self) -> bool {
+        if !self.tail.advance() {
+            return false;
+        }
+        let token = self.tail.token_mut();
+        token.text = phonetic_key(&token.text);
+        true
+    }
+
+    fn token(&self) -> &Token {
+        self.tail.token()
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        self.tail.token_mut()
+    }
+}
+
+/// Token filter that replaces each token with its prefixes of `min_gram` to `max_gram`
+/// characters, e.g. "opalab" with "o", "op", "opa", ... All prefixes keep the position of
+/// the token they came from.
+#[derive(Clone)]
+pub struct EdgeNgramFilter {
+    min_gram: usize,
+    max_gram: usize,
+}
+
+impl EdgeNgramFilter {
+    pub fn new(min_gram: usize, max_gram: usize) -> EdgeNgramFilter {
+        EdgeNgramFilter { min_gram, max_gram }
+    }
+}
+
+impl TokenFilter for EdgeNgramFilter {
+    type Tokenizer<T: Tokenizer> = EdgeNgramFilterWrapper<T>;
+
+    fn transform<T: Tokenizer>(self, tokenizer: T) -> Self::Tokenizer<T> {
+        EdgeNgramFilterWrapper {
+            tokenizer,
+            min_gram: self.min_gram,
+            max_gram: self.max_gram,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EdgeNgramFilterWrapper<T> {
+    tokenizer: T,
+    min_gram: usize,
+    max_gram: usize,
+}
+
+impl<T: Tokenizer> Tokenizer for EdgeNgramFilterWrapper<T> {
+    type TokenStream<'a> = EdgeNgramTokenStream<T::TokenStream<'a>>;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+        EdgeNgramTokenStream {
+            tail: self.tokenizer.token_stream(text),
+            min_gram: self.min_gram,
+            max_gram: self.max_gram,
+            word: String::new(),
+            prefix_ends: Vec::new(),
+            next_prefix: 0,
+            token: Token::default(),
+        }
+    }
+}
+
+pub struct EdgeNgramTokenStream<T> {
+    tail: T,
+    min_gram: usize,
+    max_gram: usize,
+    /// The token the prefixes are currently taken from.
+    word: String,
+    /// Byte offsets in `word` where each prefix ends.
+    prefix_ends: Vec<usize>,
+    next_prefix: usize,
+    token: Token,
+}
+
+impl<T: TokenStream> TokenStream for EdgeNgramTokenStream<T> {
+    fn advance(&mut self) -> bool {
+        loop {
+            if let Some(&end) = self.prefix_ends.get(self.next_prefix) {
+                self.next_prefix += 1;
+                self.token.text.clear();
+                self.token.text.push_str(&self.word[..end]);
+                return true;
+            }
+            if !self.tail.advance() {
+                return false;
+            }
+
+            self.token = self.tail.token().clone();
+            self.word.clear();
+            self.word.push_str(&self.token.text);
+            self.prefix_ends = self
+                .word
+                .char_indices()
+                .map(|(start, c)| start + c.len_utf8())
+                .enumerate()
+                .filter(|(length, _)| (self.min_gram..=self.max_gram).contains(&(length + 1)))
+                .map(|(_, end)| end)
+                .collect();
+            self.next_prefix = 0;
+        }
+    }
+
+    fn token(&self) -> &Token {
+        &self.token
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.token
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tantivy::schema::Schema;
+
+    /// Analyzes `name` as it is indexed into the `*_phonetic` fields.
+    fn phonetic(name: &str) -> Vec<String> {
+        let index = Index::create_in_ram(Schema::builder().build());
+        register(&index);
+        let mut analyzer = index.tokenizers().get(PHONETIC_TOKENIZER).unwrap();
+        let mut tokens = Vec::new();
+        analyzer
+            .token_stream(name)
+            .process(&mut |token| tokens.push(token.text.clone()));
+        tokens
+    }
+
+    #[test]
+    fn names_taken_down_by_ear_sound_the_same() {
+        for (written, heard) in [
+            ("Šuštar", "Šustar"),
+            ("Kovačič", "Kovacic"),
+            ("Jurjevec", "Jurievec"),
+        ] {
+            assert_eq!(phonetic(written), phonetic(heard), "{} {}", written, heard);
+        }
+    }
+
+    #[test]
+    fn different_names_keep_different_keys() {
+        assert_ne!(phonetic_key("novak"), phonetic_key("kovac"));
+        assert_ne!(phonetic_key("horvat"), phonetic_key("hrovat"));
+    }
+
+    #[test]
+    fn devoices_the_last_consonant() {
+        assert_eq!(phonetic_key("grad"), phonetic_key("grat"));
+        assert_eq!(phonetic_key("jurjevec"), "iurievec");
+    }
+}
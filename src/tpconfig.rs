@@ -1,8 +1,12 @@
+use crate::tokenizers::{EDGE_NGRAM_TOKENIZER, PHONETIC_TOKENIZER};
 use clap::ValueEnum;
 use std::collections::HashSet;
 use std::fmt::Formatter;
 use std::sync::LazyLock;
-use tantivy::schema::{Field, STORED, STRING, Schema, TEXT};
+use tantivy::schema::{
+    FAST, FacetOptions, Field, INDEXED, IndexRecordOption, STORED, STRING, Schema, SchemaBuilder,
+    TEXT, TextFieldIndexing, TextOptions,
+};
 
 #[derive(Hash, Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
 pub enum SourceName {
@@ -48,8 +52,38 @@ impl std::fmt::Display for SourceKind {
 pub enum Position {
     Fixed(usize, usize),
     Index(usize),
+    /// Column of a CSV file with a header, looked up by its name.
+    Column(&'static str),
+    /// Computed at index time from the other fields, see [`crate::enrich`].
+    Derived,
 }
 
+/// Field holding the facets of a document as `/<dimension>/<value>` paths.
+pub const FACETS_FIELD: &str = "facets";
+/// Stored fields whose values are also indexed as facets, see [`crate::enrich`], and `skd`,
+/// the activity code as a path from its section down, see [`crate::skd::path`].
+pub const FACET_DIMENSIONS: [&str; 8] = [
+    "legal_form",
+    "postcode",
+    "city",
+    "municipality",
+    "region",
+    "taxpayer_type",
+    "status",
+    "skd",
+];
+
+/// Fields holding the name of the entity, the first one the schema has is used.
+pub const NAME_FIELDS: [&str; 2] = ["company_name", "name"];
+/// Name fields that get an edge n-gram indexed `<field>_prefix` companion for autocomplete.
+pub const AUTOCOMPLETE_FIELDS: [&str; 2] = ["company_name", "name"];
+pub const PREFIX_SUFFIX: &str = "_prefix";
+/// Name fields that get a `<field>_phonetic` companion, searched in the phonetic match mode.
+pub const PHONETIC_FIELDS: [&str; 2] = ["company_name", "name"];
+pub const PHONETIC_SUFFIX: &str = "_phonetic";
+
+pub type SourceSchema = (Schema, HashSet<(Field, Position)>);
+
 #[derive(Debug)]
 pub struct SourceConfig {
     pub name: SourceName,
@@ -58,11 +92,130 @@ pub struct SourceConfig {
     pub zip_file_path: Option<&'static str>,
     pub data_path: Option<&'static str>,
     pub index_path: Option<&'static str>,
-    pub schema: fn() -> Option<&'static (Schema, HashSet<(Field, Position)>)>,
+    /// Bump whenever the schema or its analyzers change so existing indexes get rebuilt.
+    pub schema_version: u32,
+    pub schema: fn() -> Option<&'static SourceSchema>,
+}
+
+/// Street, house number, postcode and city parsed out of the `address` field, and the key
+/// entities registered at the same address share, see [`crate::address::Address::key`].
+fn address_fields(schema_builder: &mut SchemaBuilder) -> [(Field, Position); 5] {
+    [
+        (
+            schema_builder.add_text_field("street", TEXT | STORED),
+            Position::Derived,
+        ),
+        (
+            schema_builder.add_text_field("house_number", STRING | STORED),
+            Position::Derived,
+        ),
+        (
+            schema_builder.add_text_field("postcode", STRING | STORED),
+            Position::Derived,
+        ),
+        (
+            schema_builder.add_text_field("city", STRING | STORED),
+            Position::Derived,
+        ),
+        (
+            schema_builder.add_text_field("address_key", STRING | STORED),
+            Position::Derived,
+        ),
+    ]
+}
+
+/// Municipality, statistical region and approximate coordinates looked up by postcode.
+fn location_fields(schema_builder: &mut SchemaBuilder) -> [(Field, Position); 4] {
+    [
+        (
+            schema_builder.add_text_field("municipality", TEXT | STORED),
+            Position::Derived,
+        ),
+        (
+            schema_builder.add_text_field("region", TEXT | STORED),
+            Position::Derived,
+        ),
+        (
+            schema_builder.add_f64_field("lat", STORED | FAST),
+            Position::Derived,
+        ),
+        (
+            schema_builder.add_f64_field("lon", STORED | FAST),
+            Position::Derived,
+        ),
+    ]
+}
+
+/// SKD activity code as the source writes it, and its label looked up in [`crate::skd`].
+fn activity_fields(
+    schema_builder: &mut SchemaBuilder,
+    position: Position,
+) -> [(Field, Position); 2] {
+    [
+        (
+            schema_builder.add_text_field("activity_code", STRING | STORED),
+            position,
+        ),
+        (
+            schema_builder.add_text_field("activity", STORED),
+            Position::Derived,
+        ),
+    ]
+}
+
+fn facets_field(schema_builder: &mut SchemaBuilder) -> (Field, Position) {
+    (
+        schema_builder.add_facet_field(FACETS_FIELD, FacetOptions::default()),
+        Position::Derived,
+    )
+}
+
+/// Unstored companion of `field_name` indexed with every word prefix, see [`crate::tokenizers`].
+fn prefix_field(schema_builder: &mut SchemaBuilder, field_name: &str) -> (Field, Position) {
+    let indexing = TextFieldIndexing::default()
+        .set_tokenizer(EDGE_NGRAM_TOKENIZER)
+        .set_index_option(IndexRecordOption::WithFreqs);
+    (
+        schema_builder.add_text_field(
+            &format!("{}{}", field_name, PREFIX_SUFFIX),
+            TextOptions::default().set_indexing_options(indexing),
+        ),
+        Position::Derived,
+    )
+}
+
+/// Unstored companion of `field_name` indexed by how its words sound, see
+/// [`crate::tokenizers::phonetic_key`].
+fn phonetic_field(schema_builder: &mut SchemaBuilder, field_name: &str) -> (Field, Position) {
+    let indexing = TextFieldIndexing::default()
+        .set_tokenizer(PHONETIC_TOKENIZER)
+        .set_index_option(IndexRecordOption::WithFreqsAndPositions);
+    (
+        schema_builder.add_text_field(
+            &format!("{}{}", field_name, PHONETIC_SUFFIX),
+            TextOptions::default().set_indexing_options(indexing),
+        ),
+        Position::Derived,
+    )
 }
 
-static PRAVNE_OSEBE_SCHEMA: LazyLock<(Schema, HashSet<(Field, Position)>)> = LazyLock::new(|| {
+/// Whether the taxpayer is registered for VAT, marked with a `*` in the first column of the
+/// FURS files.
+fn vat_registered_field(schema_builder: &mut SchemaBuilder) -> (Field, Position) {
+    (
+        schema_builder.add_bool_field("vat_registered", INDEXED | STORED | FAST),
+        Position::Fixed(0, 1),
+    )
+}
+
+static PRAVNE_OSEBE_SCHEMA: LazyLock<SourceSchema> = LazyLock::new(|| {
     let mut schema_builder = Schema::builder();
+    let vat_registered = vat_registered_field(&mut schema_builder);
+    // Code of the kind of taxpayer in the second column.
+    let taxpayer_type = (
+        schema_builder.add_text_field("taxpayer_type", STRING | STORED),
+        Position::Fixed(2, 3),
+    );
     let vat_id = (
         schema_builder.add_text_field("vat_id", STRING | STORED),
         Position::Fixed(4, 12),
@@ -71,23 +224,55 @@ static PRAVNE_OSEBE_SCHEMA: LazyLock<(Schema, HashSet<(Field, Position)>)> = Laz
         schema_builder.add_text_field("company_id", STRING | STORED),
         Position::Fixed(13, 23),
     );
+    let activity = activity_fields(&mut schema_builder, Position::Fixed(24, 42));
+    let full_name = (
+        schema_builder.add_text_field("full_name", STORED),
+        Position::Fixed(42, 143),
+    );
     let company_name = (
         schema_builder.add_text_field("company_name", TEXT | STORED),
-        Position::Fixed(42, 143),
+        Position::Derived,
+    );
+    let company_name_prefix = prefix_field(&mut schema_builder, "company_name");
+    let company_name_phonetic = phonetic_field(&mut schema_builder, "company_name");
+    let legal_form = (
+        schema_builder.add_text_field("legal_form", STRING | STORED),
+        Position::Derived,
     );
     let address = (
         schema_builder.add_text_field("address", TEXT | STORED),
         Position::Fixed(143, 257),
     );
+    let address_parts = address_fields(&mut schema_builder);
+    let location = location_fields(&mut schema_builder);
+    let facets = facets_field(&mut schema_builder);
 
     (
         schema_builder.build(),
-        HashSet::from([vat_id, company_id, company_name, address]),
+        [
+            vat_registered,
+            taxpayer_type,
+            vat_id,
+            company_id,
+            full_name,
+            company_name,
+            company_name_prefix,
+            company_name_phonetic,
+            legal_form,
+            address,
+            facets,
+        ]
+        .into_iter()
+        .chain(address_parts)
+        .chain(location)
+        .chain(activity)
+        .collect(),
     )
 });
 
-static FIZICNE_OSEBE_SCHEMA: LazyLock<(Schema, HashSet<(Field, Position)>)> = LazyLock::new(|| {
+static FIZICNE_OSEBE_SCHEMA: LazyLock<SourceSchema> = LazyLock::new(|| {
     let mut schema_builder = Schema::builder();
+    let vat_registered = vat_registered_field(&mut schema_builder);
     let vat_id = (
         schema_builder.add_text_field("vat_id", STRING | STORED),
         Position::Fixed(2, 10),
@@ -96,29 +281,93 @@ static FIZICNE_OSEBE_SCHEMA: LazyLock<(Schema, HashSet<(Field, Position)>)> = La
         schema_builder.add_text_field("name", TEXT | STORED),
         Position::Fixed(11, 72),
     );
+    let name_prefix = prefix_field(&mut schema_builder, "name");
+    let name_phonetic = phonetic_field(&mut schema_builder, "name");
     let address = (
         schema_builder.add_text_field("address", TEXT | STORED),
         Position::Fixed(72, 184),
     );
+    let address_parts = address_fields(&mut schema_builder);
+    let facets = facets_field(&mut schema_builder);
+
     (
         schema_builder.build(),
-        HashSet::from([vat_id, name, address]),
+        [
+            vat_registered,
+            vat_id,
+            name,
+            name_prefix,
+            name_phonetic,
+            address,
+            facets,
+        ]
+        .into_iter()
+        .chain(address_parts)
+        .collect(),
     )
 });
 
-static PR_SCHEMA: LazyLock<(Schema, HashSet<(Field, Position)>)> = LazyLock::new(|| {
+static PR_SCHEMA: LazyLock<SourceSchema> = LazyLock::new(|| {
     let mut schema_builder = Schema::builder();
     let company_id = (
         schema_builder.add_text_field("company_id", STRING | STORED),
         Position::Index(0),
     );
+    let full_name = (
+        schema_builder.add_text_field("full_name", STORED),
+        Position::Index(1),
+    );
     let company_name = (
         schema_builder.add_text_field("company_name", TEXT | STORED),
-        Position::Index(1),
+        Position::Derived,
+    );
+    let company_name_prefix = prefix_field(&mut schema_builder, "company_name");
+    let company_name_phonetic = phonetic_field(&mut schema_builder, "company_name");
+    let legal_form = (
+        schema_builder.add_text_field("legal_form", STRING | STORED),
+        Position::Derived,
+    );
+    let address = (
+        schema_builder.add_text_field("address", TEXT | STORED),
+        Position::Column("NASLOV"),
+    );
+    let registered_at = (
+        schema_builder.add_date_field("registered_at", INDEXED | STORED | FAST),
+        Position::Column("DATUM_VPISA"),
+    );
+    let deregistered_at = (
+        schema_builder.add_date_field("deregistered_at", INDEXED | STORED | FAST),
+        Position::Column("DATUM_IZBRISA"),
     );
+    let status = (
+        schema_builder.add_text_field("status", STRING | STORED),
+        Position::Column("STATUS"),
+    );
+    let activity = activity_fields(&mut schema_builder, Position::Column("SKD"));
+    let address_parts = address_fields(&mut schema_builder);
+    let location = location_fields(&mut schema_builder);
+    let facets = facets_field(&mut schema_builder);
+
     (
         schema_builder.build(),
-        HashSet::from([company_id, company_name]),
+        [
+            company_id,
+            full_name,
+            company_name,
+            company_name_prefix,
+            company_name_phonetic,
+            legal_form,
+            address,
+            registered_at,
+            deregistered_at,
+            status,
+            facets,
+        ]
+        .into_iter()
+        .chain(address_parts)
+        .chain(location)
+        .chain(activity)
+        .collect(),
     )
 });
 
@@ -130,6 +379,7 @@ pub static CONFIG: [SourceConfig; 4] = [
         zip_file_path: Some("DURS_zavezanci_PO.txt"),
         data_path: Some("pravne_osebe.zip"),
         index_path: Some("pravne_osebe"),
+        schema_version: 15,
         schema: || Some(&PRAVNE_OSEBE_SCHEMA),
     },
     SourceConfig {
@@ -139,6 +389,7 @@ pub static CONFIG: [SourceConfig; 4] = [
         zip_file_path: Some("DURS_zavezanci_FO.txt"),
         data_path: Some("fizicne_osebe.zip"),
         index_path: Some("fizicne_osebe"),
+        schema_version: 9,
         schema: || Some(&FIZICNE_OSEBE_SCHEMA),
     },
     SourceConfig {
@@ -148,6 +399,7 @@ pub static CONFIG: [SourceConfig; 4] = [
         zip_file_path: None,
         data_path: Some("fizicne_osebe_dej.zip"),
         index_path: Some("fizicne_osebe_dej"),
+        schema_version: 1,
         schema: || None,
     },
     SourceConfig {
@@ -157,6 +409,7 @@ pub static CONFIG: [SourceConfig; 4] = [
         zip_file_path: None,
         data_path: Some("poslovni_register_slovenije.zip"),
         index_path: Some("poslovni_register_slovenije"),
+        schema_version: 14,
         schema: || Some(&PR_SCHEMA),
     },
 ];
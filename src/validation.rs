@@ -0,0 +1,288 @@
+use crate::search::ReaderMap;
+use crate::tpconfig::SourceName;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tantivy::Term;
+use tokio::sync::Mutex;
+
+fn digits(number: &str) -> Option<Vec<u32>> {
+    number.chars().map(|c| c.to_digit(10)).collect()
+}
+
+/// Strips spaces and the "SI" country prefix off a tax number, e.g. "SI 1000 0046" into
+/// "10000046".
+pub fn normalize_vat_id(raw: &str) -> String {
+    let compact: String = raw.chars().filter(|c| !c.is_whitespace()).collect();
+    match compact.get(..2) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("SI") => compact[2..].to_string(),
+        _ => compact,
+    }
+}
+
+/// Whether `vat_id` is a well formed davčna številka: eight digits, not starting with a
+/// zero, the last being a mod 11 check digit over the first seven weighted 8 down to 2.
+pub fn is_valid_vat_id(vat_id: &str) -> bool {
+    let Some(digits) = digits(vat_id) else {
+        return false;
+    };
+    if digits.len() != 8 || digits[0] == 0 {
+        return false;
+    }
+    let sum: u32 = digits[..7]
+        .iter()
+        .zip((2..=8).rev())
+        .map(|(digit, weight)| digit * weight)
+        .sum();
+    // A remainder of 1 gives a check digit of 10, written as 0. A remainder of 0 would need
+    // 11, so such numbers are never issued.
+    match 11 - sum % 11 {
+        11 => false,
+        10 => digits[7] == 0,
+        check => digits[7] == check,
+    }
+}
+
+/// Whether `company_id` is a well formed matična številka: seven digits, the last being a
+/// mod 11 check digit over the first six weighted 7 down to 2, optionally followed by the
+/// three digits of a business unit as in the tax registry.
+pub fn is_valid_company_id(company_id: &str) -> bool {
+    let Some(digits) = digits(company_id) else {
+        return false;
+    };
+    if digits.len() != 7 && digits.len() != 10 {
+        return false;
+    }
+    let sum: u32 = digits[..6]
+        .iter()
+        .zip((2..=7).rev())
+        .map(|(digit, weight)| digit * weight)
+        .sum();
+    digits[6] == (11 - sum % 11) % 10
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VatIdCheck {
+    /// The tax number without spaces and country prefix.
+    pub vat_id: String,
+    /// Well formed with a correct check digit.
+    pub valid: bool,
+    /// Listed in at least one of the indexed registries.
+    pub registered: bool,
+    /// Sources the tax number is listed in.
+    pub sources: Vec<String>,
+}
+
+/// Checks the format of `raw` and looks it up in the `vat_id` field of every index.
+pub async fn check_vat_id(readers: &Arc<Mutex<ReaderMap>>, raw: &str) -> VatIdCheck {
+    let vat_id = normalize_vat_id(raw);
+    let mut sources: Vec<SourceName> = readers
+        .lock()
+        .await
+        .iter()
+        .filter(|(_, reader)| {
+            let searcher = reader.searcher();
+            searcher
+                .schema()
+                .get_field("vat_id")
+                .ok()
+                .and_then(|field| {
+                    searcher
+                        .doc_freq(&Term::from_field_text(field, &vat_id))
+                        .ok()
+                })
+                .is_some_and(|doc_freq| doc_freq > 0)
+        })
+        .map(|(&source_name, _)| source_name)
+        .collect();
+    sources.sort_by_key(|source_name| source_name.to_string());
+
+    VatIdCheck {
+        valid: is_valid_vat_id(&vat_id),
+        registered: !sources.is_empty(),
+        sources: sources.iter().map(ToString::to_string).collect(),
+        vat_id,
+    }
+}
+
+/// An identifier pasted into the search box instead of a name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Identifier {
+    VatId(String),
+    CompanyId(String),
+}
+
+impl Identifier {
+    /// The `STRING` field the identifier is stored in and the values it may be stored as.
+    /// Company ids are stored with the three digit business unit in the tax registry and
+    /// without it in the business register, so both are looked up.
+    pub fn terms(&self) -> (&'static str, Vec<String>) {
+        match self {
+            Identifier::VatId(vat_id) => ("vat_id", vec![vat_id.clone()]),
+            Identifier::CompanyId(company_id) => {
+                let main_unit = &company_id[..7];
+                let mut values = vec![main_unit.to_string(), format!("{}000", main_unit)];
+                if !values.contains(company_id) {
+                    values.push(company_id.clone());
+                }
+                ("company_id", values)
+            }
+        }
+    }
+}
+
+/// Whether the text is a date, like "2020-01-31" or "31. 1. 2020", whose digits would
+/// otherwise pass for a tax number.
+fn is_date(text: &str) -> bool {
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    ["%Y-%m-%d", "%d.%m.%Y", "%Y.%m.%d", "%d-%m-%Y"]
+        .into_iter()
+        .any(|format| NaiveDate::parse_from_str(&text, format).is_ok())
+}
+
+/// Recognizes a tax number, with or without the "SI" prefix, or a company id with or
+/// without its business unit, written with spaces, dots or dashes between the digits or
+/// not, e.g. "SI 1000 0046" or "5300231000". Dates are not taken for either.
+pub fn detect_identifier(text: &str) -> Option<Identifier> {
+    if is_date(text) {
+        return None;
+    }
+    let compact: String = text
+        .chars()
+        .filter(|c| !c.is_whitespace() && !matches!(c, '.' | '-'))
+        .collect();
+    let (prefixed, number) = match compact.get(..2) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("SI") => (true, &compact[2..]),
+        _ => (false, compact.as_str()),
+    };
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    match (prefixed, number.len()) {
+        (_, 8) => Some(Identifier::VatId(number.to_string())),
+        (false, 7 | 10) => Some(Identifier::CompanyId(number.to_string())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_vat_ids_with_a_correct_check_digit() {
+        for vat_id in ["45884595", "98511734", "91132550", "10000046"] {
+            assert!(is_valid_vat_id(vat_id), "{}", vat_id);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_vat_ids() {
+        for vat_id in [
+            "22222222",
+            "33333333",
+            "45884596",
+            "04588459",
+            "4588459",
+            "458845950",
+            "4588459a",
+            "",
+        ] {
+            assert!(!is_valid_vat_id(vat_id), "{}", vat_id);
+        }
+    }
+
+    #[test]
+    fn accepts_company_ids_with_a_correct_check_digit() {
+        for company_id in [
+            "5300231",
+            "5025796",
+            "5043611",
+            "5860571",
+            "5014018",
+            "5300231000",
+        ] {
+            assert!(is_valid_company_id(company_id), "{}", company_id);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_company_ids() {
+        for company_id in ["1234567000", "6000000", "5300232", "530023", "53002310", ""] {
+            assert!(!is_valid_company_id(company_id), "{}", company_id);
+        }
+    }
+
+    #[test]
+    fn normalizes_vat_ids() {
+        assert_eq!(normalize_vat_id("SI 1000 0046"), "10000046");
+        assert_eq!(normalize_vat_id("si10000046"), "10000046");
+        assert_eq!(normalize_vat_id("10000046"), "10000046");
+    }
+
+    #[test]
+    fn detects_vat_ids_with_or_without_the_prefix() {
+        for text in ["10000046", "SI10000046", "si 1000 0046", "SI-10.00.00.46"] {
+            assert_eq!(
+                detect_identifier(text),
+                Some(Identifier::VatId("10000046".to_string())),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn detects_company_ids_with_or_without_the_business_unit() {
+        assert_eq!(
+            detect_identifier("5300231"),
+            Some(Identifier::CompanyId("5300231".to_string()))
+        );
+        assert_eq!(
+            detect_identifier("530 0231 000"),
+            Some(Identifier::CompanyId("5300231000".to_string()))
+        );
+        assert_eq!(detect_identifier("SI5300231"), None);
+    }
+
+    #[test]
+    fn does_not_take_dates_or_names_for_identifiers() {
+        for text in [
+            "2020-01-31",
+            "31.01.2020",
+            "31. 1. 2020",
+            "2020.01.31",
+            "OPALAB",
+            "1000",
+            "",
+        ] {
+            assert_eq!(detect_identifier(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn looks_up_company_ids_with_and_without_the_business_unit() {
+        assert_eq!(
+            Identifier::CompanyId("5300231".to_string()).terms(),
+            (
+                "company_id",
+                vec!["5300231".to_string(), "5300231000".to_string()]
+            )
+        );
+        assert_eq!(
+            Identifier::CompanyId("5300231001".to_string()).terms(),
+            (
+                "company_id",
+                vec![
+                    "5300231".to_string(),
+                    "5300231000".to_string(),
+                    "5300231001".to_string()
+                ]
+            )
+        );
+        assert_eq!(
+            Identifier::VatId("10000046".to_string()).terms(),
+            ("vat_id", vec!["10000046".to_string()])
+        );
+    }
+}